@@ -0,0 +1,124 @@
+use crate::constants::*;
+use crate::engine::Engine;
+use crate::game::{Move, State};
+use crate::record::{GameRecord, Outcome, outcome};
+use crate::search::SearchInfo;
+use std::time::{Duration, Instant};
+
+// Search of one position. The score is for white, the result itself when the game is over.
+pub struct Analysis {
+    pub ply: usize,
+    pub state: State,
+    pub score: i32,
+    pub info: Option<SearchInfo>
+}
+
+// Move after which the evaluation of the side that played it dropped by at least the threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blunder {
+    pub ply: usize,
+    pub color: String,
+    pub played: Move,
+    pub best: Option<Move>,
+    pub loss: i64,
+    // Score for white of the position that revealed it
+    pub score: i32
+}
+
+pub fn analyze_position(state: &State, ply: usize, engine: &mut Engine, time: Duration) -> Analysis {
+    let (score, info) = match outcome(state).0 {
+        Outcome::WhiteWin => (i32::MAX, None),
+        Outcome::BlackWin => (i32::MIN, None),
+        Outcome::Draw => (0, None),
+        Outcome::Unfinished => {
+            let info = engine.search(state, Instant::now() + time);
+            (info.as_ref().map_or(0, |info| info.score), info)
+        }
+    };
+    Analysis { ply, state: state.clone(), score, info }
+}
+
+// Evaluation lost by color with a move that changed the score for white from before to after
+pub fn loss(before: i32, after: i32, color: &str) -> i64 {
+    let change = after as i64 - before as i64;
+    if color == WHITE { -change } else { change }
+}
+
+// Blunder of the move played in before, when the evaluation of its side drops by threshold
+// and the engine preferred another move
+fn blunder(record: &GameRecord, before: &Analysis, after: &Analysis, threshold: i64) -> Option<Blunder> {
+    let color = &before.state.turn;
+    let loss = loss(before.score, after.score, color);
+    let played = record.moves[before.ply];
+    let best = before.info.as_ref().and_then(|info| info.best_move());
+    // The engine would have played it too, the loss lies beyond its horizon
+    if loss < threshold || best == Some(played) {
+        return None;
+    }
+    Some(Blunder { ply: before.ply, color: color.clone(), played, best, loss, score: after.score })
+}
+
+// Searches every position of the game for time each and returns the moves that lost at least
+// threshold. The engine should not keep the best iteration, so that every score comes from
+// the deepest one. Scores still depend on the side to move, so a move is judged by the next
+// position with the same side to move, or by the result when it ends the game. report gets
+// each analysis with the blunders it reveals.
+pub fn review_game<F: FnMut(&Analysis, &[Blunder])>(record: &GameRecord, engine: &mut Engine, time: Duration, threshold: i64, mut report: F) -> Result<Vec<Blunder>, String> {
+    let mut blunders: Vec<Blunder> = vec![];
+    let mut analyses: Vec<Analysis> = vec![];
+    for (ply, state) in record.states()?.iter().enumerate() {
+        let analysis = analyze_position(state, ply, engine, time);
+        let mut found: Vec<Blunder> = vec![];
+        if ply >= 2 {
+            found.extend(blunder(record, &analyses[ply - 2], &analysis, threshold));
+        }
+        if ply >= 1 && outcome(state).0 != Outcome::Unfinished {
+            found.extend(blunder(record, &analyses[ply - 1], &analysis, threshold));
+        }
+        report(&analysis, &found);
+        blunders.extend(found);
+        analyses.push(analysis);
+    }
+    Ok(blunders)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::record::escape_game;
+
+    #[test]
+    fn test_loss() {
+        assert_eq!(loss(20, -30, WHITE), 50);
+        assert_eq!(loss(20, -30, BLACK), -50);
+        assert_eq!(loss(0, i32::MAX, BLACK), i32::MAX as i64);
+        assert_eq!(loss(i32::MAX, i32::MIN, WHITE), (1 << 32) - 1);
+    }
+
+    #[test]
+    fn test_review_game() {
+        // Black ignores the king on e3, which escapes to a3
+        let record = escape_game();
+
+        let mut config = EngineConfig { depth: 2, tt_size_mb: 1, ..EngineConfig::default() };
+        config.search.best_iteration = false;
+        let mut engine = Engine::new(config);
+        let mut analyses: Vec<(usize, i32, bool)> = vec![];
+        let mut reported = 0;
+        let blunders = review_game(&record, &mut engine, Duration::from_secs(10), BLUNDER_THRESHOLD, |analysis, found| {
+            analyses.push((analysis.ply, analysis.score, analysis.info.is_some()));
+            reported += found.len();
+            assert!(found.iter().all(|blunder| blunder.ply + 2 >= analysis.ply && blunder.ply < analysis.ply));
+        }).unwrap();
+        assert_eq!(reported, blunders.len());
+        assert_eq!(analyses.len(), 8);
+        assert_eq!(analyses[7], (7, i32::MAX, false));
+        assert!(analyses[..7].iter().all(|analysis| analysis.2));
+
+        let blunder = blunders.iter().find(|blunder| blunder.ply == 5).unwrap();
+        assert_eq!((blunder.color.as_str(), blunder.played.to_string()), (BLACK, "d2->c2".to_string()));
+        assert_ne!(blunder.best, Some(blunder.played));
+        assert!(blunders.iter().all(|blunder| blunder.loss >= BLUNDER_THRESHOLD));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::constants::*;
+use crate::engine::{Engine, EngineConfig, EngineKind};
+use crate::eval::{evaluator_by_name, EvalParams};
+use crate::game::{Move, State};
+use crate::record::{GameRecord, Outcome, outcome};
+use crate::rules::legal_moves;
+use crate::ruleset::Ruleset;
+use crate::search::random_move;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::Local;
+
+// One side of an arena match: an engine configuration and its time per move
+#[derive(Clone, Debug)]
+pub struct Contender {
+    // Specification the contender was parsed from, used as its name in the records
+    pub name: String,
+    pub config: EngineConfig,
+    pub move_time: Duration
+}
+
+// Comma separated options, e.g. "alphabeta,depth=4,time=200,no-lmr,eval=tuned.json". Missing
+// options keep the engine defaults, with a smaller transposition table since many engines run
+// at once.
+impl FromStr for Contender {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Contender, String> {
+        let mut config = EngineConfig { tt_size_mb: ARENA_TT_SIZE_MB, ..EngineConfig::default() };
+        let mut move_time = Duration::from_millis(ARENA_MOVE_TIME_MS);
+        for option in s.split(',').map(|option| option.trim()).filter(|option| !option.is_empty()) {
+            let (key, value) = match option.find('=') {
+                Some(i) => (&option[..i], Some(&option[i + 1..])),
+                None => (option, None)
+            };
+            let number = || value.and_then(|v| v.parse::<u64>().ok()).ok_or(format!("bad value in {}", option));
+            match (key, value) {
+                ("alphabeta", None) | ("mcts", None) => config.kind = key.parse::<EngineKind>()?,
+                ("depth", Some(_)) => config.depth = number()? as u32,
+                ("time", Some(_)) => move_time = Duration::from_millis(number()?),
+                ("hash", Some(_)) => config.tt_size_mb = number()? as usize,
+                ("threads", Some(_)) => config.threads = (number()? as usize).max(1),
+                ("playout", Some(v)) => config.mcts.playout = v.parse()?,
+                ("eval", Some(v)) => config.eval = EvalParams::load(Path::new(v))?,
+                ("white-eval", Some(v)) => config.white_evaluator = evaluator_by_name(v).ok_or(format!("unknown evaluator {}", v))?,
+                ("black-eval", Some(v)) => config.black_evaluator = evaluator_by_name(v).ok_or(format!("unknown evaluator {}", v))?,
+                ("no-ordering", None) => config.search.move_ordering = false,
+                ("no-quiescence", None) => config.search.quiescence = false,
+                ("no-aspiration", None) => config.search.aspiration = false,
+                ("no-pvs", None) => config.search.pvs = false,
+                ("no-null-move", None) => config.search.null_move = false,
+                ("no-lmr", None) => config.search.late_move_reductions = false,
+                _ => return Err(format!("unknown engine option {}", option))
+            }
+        }
+        Ok(Contender { name: s.to_string(), config, move_time })
+    }
+}
+
+// Results of the first contender
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32
+}
+
+impl Score {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn add(&mut self, result: Outcome, first_is_white: bool) {
+        match (result, first_is_white) {
+            (Outcome::WhiteWin, true) | (Outcome::BlackWin, false) => self.wins += 1,
+            (Outcome::WhiteWin, false) | (Outcome::BlackWin, true) => self.losses += 1,
+            _ => self.draws += 1
+        }
+    }
+
+    // Mean points per game and variance of the points of one game, with pseudo games of each
+    // result added to the observed ones
+    fn mean_variance(&self, pseudo_games: f64) -> (f64, f64) {
+        let wins = self.wins as f64 + pseudo_games;
+        let draws = self.draws as f64 + pseudo_games;
+        let losses = self.losses as f64 + pseudo_games;
+        let games = wins + draws + losses;
+        let mean = (wins + draws / 2.0) / games;
+        let variance = (wins * (1.0 - mean).powi(2)
+            + draws * (0.5 - mean).powi(2)
+            + losses * mean.powi(2)) / games;
+        (mean, variance)
+    }
+
+    // Elo difference of the first contender and the half width of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (0.0, f64::INFINITY);
+        }
+        let (mean, variance) = self.mean_variance(0.0);
+        let deviation = 1.96 * (variance / self.games() as f64).sqrt();
+        let low = elo_of((mean - deviation).max(0.0));
+        let high = elo_of((mean + deviation).min(1.0));
+        (elo_of(mean), (high - low) / 2.0)
+    }
+
+    // Log-likelihood ratio of the first contender being elo1 rather than elo0 stronger, with
+    // the normal approximation of the trinomial results. Pseudo games keep the variance above
+    // 0 when every game ended the same way.
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        let (mean, variance) = self.mean_variance(SPRT_PSEUDO_GAMES);
+        let (score0, score1) = (score_of(elo0), score_of(elo1));
+        self.games() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+// Expected points per game of an Elo difference, and its inverse
+fn score_of(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_of(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Undecided,
+    // The first contender is at most elo0 stronger
+    H0,
+    // The first contender is at least elo1 stronger
+    H1
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = match self {
+            Verdict::Undecided => "undecided",
+            Verdict::H0 => "H0 accepted",
+            Verdict::H1 => "H1 accepted"
+        };
+        write!(f, "{}", verdict)
+    }
+}
+
+// Sequential probability ratio test of H0: elo = elo0 against H1: elo = elo1, with alpha and
+// beta the probabilities of accepting H1 when H0 is true and H0 when H1 is true
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64
+}
+
+impl Sprt {
+    // Log-likelihood ratios below the first bound accept H0, above the second accept H1
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    pub fn verdict(&self, score: &Score) -> Verdict {
+        let llr = score.llr(self.elo0, self.elo1);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            Verdict::H0
+        } else if llr >= upper {
+            Verdict::H1
+        } else {
+            Verdict::Undecided
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaConfig {
+    pub rules: &'static Ruleset,
+    pub games: usize,
+    // Games played at the same time
+    pub jobs: usize,
+    pub random_plies: usize,
+    pub max_plies: usize,
+    // Stops as soon as the test is decided, None plays every game
+    pub sprt: Option<Sprt>
+}
+
+// Random moves that start the games of a pair, the same for both color assignments
+fn opening(rules: &'static Ruleset, pair: usize, plies: usize) -> Vec<Move> {
+    let mut rng = StdRng::seed_from_u64(pair as u64);
+    let mut state = State::with_rules(rules.first_turn.to_string(), rules);
+    let mut moves: Vec<Move> = vec![];
+    while moves.len() < plies && outcome(&state).0 == Outcome::Unfinished {
+        let m = random_move(&state, &mut rng);
+        state.make_move(&m);
+        moves.push(m);
+    }
+    moves
+}
+
+// Plays one game from the opening moves, a draw once max_plies moves were played
+pub fn play_game(rules: &'static Ruleset, white: &Contender, black: &Contender, opening: &[Move], max_plies: usize) -> GameRecord {
+    let mut record = GameRecord::new(&white.name, &black.name, &Local::now().format("%Y.%m.%d").to_string(), rules);
+    let mut engines = [Engine::new(white.config), Engine::new(black.config)];
+    let mut state = State::with_rules(rules.first_turn.to_string(), rules);
+    for m in opening {
+        state.make_move(m);
+        record.moves.push(*m);
+    }
+    loop {
+        if outcome(&state).0 != Outcome::Unfinished {
+            record.finish(&state);
+            return record;
+        }
+        if record.moves.len() >= max_plies {
+            record.result = Outcome::Draw;
+            record.termination = "move limit".to_string();
+            return record;
+        }
+        let (engine, move_time) = if state.turn == WHITE { (&mut engines[0], white.move_time) } else { (&mut engines[1], black.move_time) };
+        let m = engine.search(&state, Instant::now() + move_time)
+            .and_then(|info| info.best_move())
+            .unwrap_or_else(|| legal_moves(&state)[0]);
+        state.make_move(&m);
+        record.moves.push(m);
+    }
+}
+
+// Plays the games of a match on config.jobs threads, the first contender is white in even
+// games. Every finished game is passed to report with the score so far, in the order they end.
+pub fn run_arena<F: FnMut(usize, &GameRecord, &Score)>(first: &Contender, second: &Contender, config: &ArenaConfig, mut report: F) -> Score {
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut score = Score::default();
+    let (sender, receiver) = mpsc::channel::<(usize, GameRecord)>();
+    thread::scope(|scope| {
+        for _ in 0..config.jobs.max(1) {
+            let sender = sender.clone();
+            let (next_game, stop) = (&next_game, &stop);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= config.games {
+                        break;
+                    }
+                    let moves = opening(config.rules, game / 2, config.random_plies);
+                    let (white, black) = if game % 2 == 0 { (first, second) } else { (second, first) };
+                    let record = play_game(config.rules, white, black, &moves, config.max_plies);
+                    sender.send((game, record)).unwrap();
+                }
+            });
+        }
+        drop(sender);
+        for (game, record) in receiver {
+            score.add(record.result, game % 2 == 0);
+            report(game, &record, &score);
+            if let Some(sprt) = config.sprt {
+                if sprt.verdict(&score) != Verdict::Undecided {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+    score
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ruleset::BRANDUBH;
+
+    #[test]
+    fn test_contender() {
+        let contender: Contender = "mcts,time=50,playout=random".parse().unwrap();
+        assert_eq!(contender.config.kind, EngineKind::Mcts);
+        assert_eq!(contender.move_time, Duration::from_millis(50));
+        let contender: Contender = "depth=3,no-lmr,hash=4".parse().unwrap();
+        assert_eq!(contender.config.kind, EngineKind::AlphaBeta);
+        assert_eq!((contender.config.depth, contender.config.tt_size_mb), (3, 4));
+        assert!(!contender.config.search.late_move_reductions);
+        assert!(contender.config.search.null_move);
+        assert!("depth=deep".parse::<Contender>().is_err());
+        assert!("no-search".parse::<Contender>().is_err());
+        assert!("eval=/nonexistent/params.json".parse::<Contender>().is_err());
+        let contender: Contender = "black-eval=blockade".parse().unwrap();
+        assert_eq!((contender.config.white_evaluator.name(), contender.config.black_evaluator.name()), ("escape", "blockade"));
+        assert!("white-eval=material".parse::<Contender>().is_err());
+    }
+
+    #[test]
+    fn test_elo() {
+        let even = Score { wins: 30, draws: 40, losses: 30 };
+        assert_eq!(even.elo().0, 0.0);
+        let score = Score { wins: 60, draws: 20, losses: 20 };
+        let (elo, margin) = score.elo();
+        assert!((elo - 147.19).abs() < 0.01);
+        assert!(margin > 50.0 && margin < 100.0);
+        let more_games = Score { wins: 600, draws: 200, losses: 200 };
+        assert!(more_games.elo().1 < margin / 3.0);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt { elo0: 0.0, elo1: 20.0, alpha: 0.05, beta: 0.05 };
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 0.001 && (lower + 2.944).abs() < 0.001);
+        assert_eq!(sprt.verdict(&Score::default()), Verdict::Undecided);
+        assert_eq!(sprt.verdict(&Score { wins: 5, draws: 0, losses: 0 }), Verdict::Undecided);
+        assert_eq!(sprt.verdict(&Score { wins: 20, draws: 0, losses: 0 }), Verdict::H1);
+        assert_eq!(sprt.verdict(&Score { wins: 0, draws: 0, losses: 20 }), Verdict::H0);
+        assert_eq!(sprt.verdict(&Score { wins: 12, draws: 0, losses: 8 }), Verdict::Undecided);
+        assert_eq!(sprt.verdict(&Score { wins: 75, draws: 0, losses: 25 }), Verdict::H1);
+        assert_eq!(sprt.verdict(&Score { wins: 1000, draws: 0, losses: 1000 }), Verdict::H0);
+    }
+
+    #[test]
+    fn test_arena() {
+        let first: Contender = "depth=1,time=20".parse().unwrap();
+        let second: Contender = "depth=2,time=20".parse().unwrap();
+        let config = ArenaConfig { rules: &BRANDUBH, games: 4, jobs: 2, random_plies: 2, max_plies: 30, sprt: None };
+        let mut reported: Vec<usize> = vec![];
+        let score = run_arena(&first, &second, &config, |game, record, _| {
+            let white = if game % 2 == 0 { &first.name } else { &second.name };
+            assert_eq!(&record.white, white);
+            assert_eq!(record.moves[..2], opening(&BRANDUBH, game / 2, 2)[..]);
+            assert!(record.states().is_ok());
+            assert_ne!(record.result, Outcome::Unfinished);
+            reported.push(game);
+        });
+        reported.sort();
+        assert_eq!(reported, vec![0, 1, 2, 3]);
+        assert_eq!(score.games(), 4);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::game::Position;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+// Largest supported board, smaller boards use the top left corner of the grid
+pub const MAX_SIZE: u32 = 13;
+pub const MIN_SIZE: u32 = 7;
+pub const CELLS: u32 = MAX_SIZE * MAX_SIZE;
+
+const WORDS: usize = 3;
+
+// One bit per cell of a MAX_SIZE grid, bit index is y * MAX_SIZE + x
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bitboard([u64; WORDS]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Right,
+    Left
+}
+
+// Directions in the same order as Board::surrounding_cells
+pub const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Right, Direction::Left];
+
+const fn bit(index: u32) -> [u64; WORDS] {
+    let mut words = [0u64; WORDS];
+    words[(index / 64) as usize] = 1u64 << (index % 64);
+    words
+}
+
+const fn or(a: [u64; WORDS], b: [u64; WORDS]) -> [u64; WORDS] {
+    [a[0] | b[0], a[1] | b[1], a[2] | b[2]]
+}
+
+const fn column_mask(x: u32) -> Bitboard {
+    let mut mask = [0u64; WORDS];
+    let mut y = 0;
+    while y < MAX_SIZE {
+        mask = or(mask, bit(y * MAX_SIZE + x));
+        y += 1;
+    }
+    Bitboard(mask)
+}
+
+const fn without(a: Bitboard, b: Bitboard) -> Bitboard {
+    Bitboard([a.0[0] & !b.0[0], a.0[1] & !b.0[1], a.0[2] & !b.0[2]])
+}
+
+const FULL: Bitboard = Bitboard::square(MAX_SIZE);
+const NOT_FIRST_COLUMN: Bitboard = without(FULL, column_mask(0));
+const NOT_LAST_COLUMN: Bitboard = without(FULL, column_mask(MAX_SIZE - 1));
+
+// Index of a cell in bitboards and per-cell tables
+pub fn cell_index(p: Position) -> usize {
+    (p.y * MAX_SIZE + p.x) as usize
+}
+
+// Cell next to the given one in a direction, None when it leaves the grid
+pub fn step(p: Position, direction: Direction) -> Option<Position> {
+    match direction {
+        Direction::Up if p.y > 0 => Some(Position { x: p.x, y: p.y - 1 }),
+        Direction::Down if p.y + 1 < MAX_SIZE => Some(Position { x: p.x, y: p.y + 1 }),
+        Direction::Right if p.x + 1 < MAX_SIZE => Some(Position { x: p.x + 1, y: p.y }),
+        Direction::Left if p.x > 0 => Some(Position { x: p.x - 1, y: p.y }),
+        _ => None
+    }
+}
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard([0; WORDS]);
+
+    // Cells of a board of the given size
+    pub const fn square(size: u32) -> Bitboard {
+        let mut mask = [0u64; WORDS];
+        let mut y = 0;
+        while y < size {
+            let mut x = 0;
+            while x < size {
+                mask = or(mask, bit(y * MAX_SIZE + x));
+                x += 1;
+            }
+            y += 1;
+        }
+        Bitboard(mask)
+    }
+
+    // Cells holding the given symbol in a layout of one string per row
+    pub const fn from_layout(rows: &[&str], symbol: u8) -> Bitboard {
+        let mut mask = [0u64; WORDS];
+        let mut y = 0;
+        while y < rows.len() {
+            let row = rows[y].as_bytes();
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] == symbol {
+                    mask = or(mask, bit(y as u32 * MAX_SIZE + x as u32));
+                }
+                x += 1;
+            }
+            y += 1;
+        }
+        Bitboard(mask)
+    }
+
+    pub fn from_position(p: Position) -> Bitboard {
+        Bitboard(bit(p.y * MAX_SIZE + p.x))
+    }
+
+    pub fn contains(self, p: Position) -> bool {
+        let index = p.y * MAX_SIZE + p.x;
+        self.0[(index / 64) as usize] & (1u64 << (index % 64)) != 0
+    }
+
+    pub fn set(&mut self, p: Position) {
+        let index = p.y * MAX_SIZE + p.x;
+        self.0[(index / 64) as usize] |= 1u64 << (index % 64);
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // Returns the lowest cell in row-major order
+    pub fn first(self) -> Option<Position> {
+        for (i, word) in self.0.iter().enumerate() {
+            if *word != 0 {
+                let index = i as u32 * 64 + word.trailing_zeros();
+                return Some(Position { x: index % MAX_SIZE, y: index / MAX_SIZE });
+            }
+        }
+        None
+    }
+
+    // Bits moved towards higher indices, 0 < n < 64
+    fn shl(self, n: u32) -> Bitboard {
+        let w = self.0;
+        Bitboard([w[0] << n, w[1] << n | w[0] >> (64 - n), w[2] << n | w[1] >> (64 - n)])
+    }
+
+    // Bits moved towards lower indices, 0 < n < 64
+    fn shr(self, n: u32) -> Bitboard {
+        let w = self.0;
+        Bitboard([w[0] >> n | w[1] << (64 - n), w[1] >> n | w[2] << (64 - n), w[2] >> n])
+    }
+
+    // Moves every cell by one in the given direction, dropping cells that leave the grid.
+    // On boards smaller than MAX_SIZE cells can land outside the board, see Ruleset::area.
+    pub fn shift(self, direction: Direction) -> Bitboard {
+        match direction {
+            Direction::Up => self.shr(MAX_SIZE),
+            Direction::Down => self.shl(MAX_SIZE) & FULL,
+            Direction::Right => (self & NOT_LAST_COLUMN).shl(1),
+            Direction::Left => (self & NOT_FIRST_COLUMN).shr(1)
+        }
+    }
+
+    // Cells orthogonally adjacent to any cell of the bitboard
+    pub fn neighbours(self) -> Bitboard {
+        self.shift(Direction::Up) | self.shift(Direction::Down) |
+            self.shift(Direction::Right) | self.shift(Direction::Left)
+    }
+
+    pub fn iter(self) -> BitboardIter {
+        BitboardIter(self)
+    }
+}
+
+pub struct BitboardIter(Bitboard);
+
+impl Iterator for BitboardIter {
+    type Item = Position;
+
+    fn next(&mut self) -> Option<Position> {
+        for (i, word) in (self.0).0.iter_mut().enumerate() {
+            if *word != 0 {
+                let index = i as u32 * 64 + word.trailing_zeros();
+                *word &= *word - 1;
+                return Some(Position { x: index % MAX_SIZE, y: index / MAX_SIZE });
+            }
+        }
+        None
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, rhs: Bitboard) -> Bitboard {
+        let (a, b) = (self.0, rhs.0);
+        Bitboard([a[0] & b[0], a[1] & b[1], a[2] & b[2]])
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, rhs: Bitboard) -> Bitboard {
+        let (a, b) = (self.0, rhs.0);
+        Bitboard([a[0] | b[0], a[1] | b[1], a[2] | b[2]])
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, rhs: Bitboard) -> Bitboard {
+        let (a, b) = (self.0, rhs.0);
+        Bitboard([a[0] ^ b[0], a[1] ^ b[1], a[2] ^ b[2]])
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        without(FULL, self)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Bitboard) {
+        for (word, other) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *word &= other;
+        }
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Bitboard) {
+        for (word, other) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *word |= other;
+        }
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Bitboard) {
+        for (word, other) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *word ^= other;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shift() {
+        let last = MAX_SIZE - 1;
+        let corner = Bitboard::from_position(Position { x: last, y: 0 });
+        assert!(corner.shift(Direction::Right).is_empty());
+        assert!(corner.shift(Direction::Up).is_empty());
+        assert_eq!(corner.shift(Direction::Left).first(), Some(Position { x: last - 1, y: 0 }));
+        assert_eq!(corner.shift(Direction::Down).first(), Some(Position { x: last, y: 1 }));
+
+        let corner = Bitboard::from_position(Position { x: 0, y: last });
+        assert!(corner.shift(Direction::Left).is_empty());
+        assert!(corner.shift(Direction::Down).is_empty());
+        assert_eq!(corner.neighbours().count(), 2);
+
+        // Cells crossing the last word boundary
+        let cell = Bitboard::from_position(Position { x: 10, y: 9 });
+        assert_eq!(cell.shift(Direction::Down).first(), Some(Position { x: 10, y: 10 }));
+        assert_eq!(cell.shift(Direction::Down).shift(Direction::Up), cell);
+        assert_eq!(cell.shift(Direction::Right).shift(Direction::Left), cell);
+
+        // On a smaller board cells leave the board without leaving the grid
+        let area = Bitboard::square(9);
+        assert_eq!(area.count(), 81);
+        let corner = Bitboard::from_position(Position { x: 8, y: 8 });
+        assert!((corner.shift(Direction::Right) & area).is_empty());
+        assert!((corner.shift(Direction::Down) & area).is_empty());
+        assert_eq!((corner.neighbours() & area).count(), 2);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut bitboard = Bitboard::EMPTY;
+        bitboard.set(Position { x: 3, y: 5 });
+        bitboard.set(Position { x: 1, y: 0 });
+        bitboard.set(Position { x: 8, y: 8 });
+        bitboard.set(Position { x: 12, y: 12 });
+        let cells: Vec<Position> = bitboard.iter().collect();
+        assert_eq!(cells, vec![Position { x: 1, y: 0 }, Position { x: 3, y: 5 }, Position { x: 8, y: 8 }, Position { x: 12, y: 12 }]);
+        assert_eq!(bitboard.count(), 4);
+        assert_eq!((!bitboard).count(), CELLS - 4);
+    }
+}
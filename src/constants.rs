@@ -10,6 +10,62 @@ pub const BLACK: &str = "black";
 
 pub const DEFAULT_WHITE_PORT: u32 = 5800;
 pub const DEFAULT_BLACK_PORT: u32 = 5801;
+// Longest message accepted from a peer, in bytes
+pub const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+
+// Transposition table size in megabytes
+pub const DEFAULT_TT_SIZE_MB: usize = 64;
+
+// Nodes a quiescence search may visit below one leaf
+pub const QUIESCENCE_NODE_LIMIT: u32 = 64;
+
+// Half width of the first aspiration window, and the width after which it is fully open
+pub const ASPIRATION_WINDOW: i32 = 50;
+pub const ASPIRATION_MAX_WINDOW: i32 = 5000;
+
+// Null-move pruning: depth reduction of the null move search, least remaining depth, and
+// least number of legal moves of the side to move
+pub const NULL_MOVE_REDUCTION: u32 = 2;
+pub const NULL_MOVE_MIN_DEPTH: u32 = 3;
+pub const NULL_MOVE_MIN_MOVES: usize = 10;
+
+// Late move reductions: least remaining depth and index in the ordered moves
+pub const LMR_MIN_DEPTH: u32 = 3;
+pub const LMR_MIN_MOVE_INDEX: usize = 4;
+
+// Maximum depth of the alpha-beta iterative deepening
+pub const DEFAULT_SEARCH_DEPTH: u32 = 6;
+
+// Search threads, one keeps the search deterministic
+pub const DEFAULT_THREADS: usize = 1;
+
+// Monte Carlo Tree Search
+pub const MCTS_EXPLORATION: f64 = 1.41;
+// Plies after which a playout is scored with the heuristic
+pub const MCTS_PLAYOUT_DEPTH: u32 = 60;
+// Random moves compared with the heuristic at each step of a heuristic playout
+pub const MCTS_PLAYOUT_CANDIDATES: u32 = 4;
+// Heuristic value that maps to a 73% white win probability
+pub const MCTS_EVAL_SCALE: f64 = 400.0;
+
+// Arena: thinking time per move in milliseconds and transposition table size of each engine
+pub const ARENA_MOVE_TIME_MS: u64 = 100;
+pub const ARENA_TT_SIZE_MB: usize = 16;
+// Random plies played before the engines take over, and plies after which a game is a draw
+pub const ARENA_RANDOM_PLIES: usize = 4;
+pub const ARENA_MAX_PLIES: usize = 300;
+// Games of each result added to the observed ones when the SPRT estimates its variance, so
+// that a match without losses or without wins still moves the test
+pub const SPRT_PSEUDO_GAMES: f64 = 0.5;
+
+// Tuner: plies skipped at the start of each game, first step of the weight search, and most
+// passes over the weights
+pub const TUNER_SKIP_PLIES: usize = 8;
+pub const TUNER_FIRST_STEP: i32 = 16;
+pub const TUNER_MAX_PASSES: usize = 100;
+
+// Analysis: evaluation a move must lose to be reported as a blunder
+pub const BLUNDER_THRESHOLD: i64 = 150;
 
 // Cell contents
 pub const W: u32 = 1; // White
@@ -25,27 +81,4 @@ pub const F: u32 = 40; // Escape Free
 
 // pub const COLUMNS: u32 = 9;
 // pub const ROWS: u32 = 9;
-pub const BOARD_COLUMNS: [char; 9] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i'];
-pub const BOARD: [[u32; 9]; 9] = [
-    [R, F, F, C, C, C, F, F, R],
-    [F, R, R, R, C, R, R, R, F],
-    [F, R, R, R, R, R, R, R, F],
-    [C, R, R, R, R, R, R, R, C],
-    [C, C, R, R, T, R, R, C, C],
-    [C, R, R, R, R, R, R, R, C],
-    [F, R, R, R, R, R, R, R, F],
-    [F, R, R, R, C, R, R, R, F],
-    [R, F, F, C, C, C, F, F, R]
-];
-
-pub const INITIAL_BOARD: [[u32; 9]; 9] = [
-    [0, 0, 0, B, B, B, 0, 0, 0],
-    [0, 0, 0, 0, B, 0, 0, 0, 0],
-    [0, 0, 0, 0, W, 0, 0, 0, 0],
-    [B, 0, 0, 0, W, 0, 0, 0, B],
-    [B, B, W, W, K, W, W, B, B],
-    [B, 0, 0, 0, W, 0, 0, 0, B],
-    [0, 0, 0, 0, W, 0, 0, 0, 0],
-    [0, 0, 0, 0, B, 0, 0, 0, 0],
-    [0, 0, 0, B, B, B, 0, 0, 0]
-];
+pub const BOARD_COLUMNS: [char; 13] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm'];
@@ -0,0 +1,113 @@
+use crate::constants::*;
+use crate::game::State;
+use crate::eval::{EvalParams, Evaluator, EscapeEvaluator};
+use crate::mcts::{mcts_search, MctsConfig};
+use crate::search::{iterative_time_bound_alpha_beta_search, SearchConfig, SearchContext, SearchInfo};
+use std::str::FromStr;
+use std::time::Instant;
+
+// Search algorithm used to choose moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineKind {
+    AlphaBeta,
+    Mcts
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EngineKind, String> {
+        match s {
+            "alphabeta" => Ok(EngineKind::AlphaBeta),
+            "mcts" => Ok(EngineKind::Mcts),
+            _ => Err(format!("unknown engine {}, expected alphabeta or mcts", s))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EngineConfig {
+    pub kind: EngineKind,
+    // Maximum depth of the alpha-beta iterative deepening
+    pub depth: u32,
+    pub tt_size_mb: usize,
+    pub threads: usize,
+    pub search: SearchConfig,
+    pub mcts: MctsConfig,
+    // Evaluation used when the engine plays white or black, and its weights
+    pub white_evaluator: &'static dyn Evaluator,
+    pub black_evaluator: &'static dyn Evaluator,
+    pub eval: EvalParams
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            kind: EngineKind::AlphaBeta,
+            depth: DEFAULT_SEARCH_DEPTH,
+            tt_size_mb: DEFAULT_TT_SIZE_MB,
+            threads: DEFAULT_THREADS,
+            search: SearchConfig::default(),
+            mcts: MctsConfig::default(),
+            white_evaluator: &EscapeEvaluator,
+            black_evaluator: &EscapeEvaluator,
+            eval: EvalParams::default()
+        }
+    }
+}
+
+// A configured searcher, keeping its tables between moves
+pub struct Engine {
+    pub config: EngineConfig,
+    pub context: SearchContext
+}
+
+impl Engine {
+    pub fn new(config: EngineConfig) -> Engine {
+        let mut context = SearchContext::new(config.tt_size_mb);
+        context.threads = config.threads;
+        context.config = config.search;
+        context.eval = config.eval;
+        Engine { config, context }
+    }
+
+    // Searches the state until the deadline, None when there is no legal move
+    pub fn search(&mut self, state: &State, end_instant: Instant) -> Option<SearchInfo> {
+        let evaluator = if state.color == WHITE { self.config.white_evaluator } else { self.config.black_evaluator };
+        // Scores stored by the other evaluator would mislead this one
+        if evaluator.name() != self.context.evaluator.name() {
+            self.context.tt.clear();
+            self.context.evaluator = evaluator;
+        }
+        match self.config.kind {
+            EngineKind::AlphaBeta => iterative_time_bound_alpha_beta_search(state, self.config.depth, end_instant, &mut self.context),
+            EngineKind::Mcts => mcts_search(state, end_instant, &self.config.mcts, self.context.evaluator, &self.config.eval)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::BlockadeEvaluator;
+    use crate::rules::legal_moves;
+    use std::time::Duration;
+
+    #[test]
+    fn test_evaluator_switch() {
+        let config = EngineConfig { depth: 2, tt_size_mb: 1, black_evaluator: &BlockadeEvaluator, ..EngineConfig::default() };
+        let mut engine = Engine::new(config);
+        let end_instant = Instant::now() + Duration::from_secs(60);
+        let white = State::init(WHITE.to_string());
+        engine.search(&white, end_instant);
+        assert!(engine.context.tt.probe(white.board.key()).is_some());
+
+        // The scores of white's evaluator are dropped before black searches with its own
+        let mut black = white.clone();
+        black.make_move(&legal_moves(&white)[0]);
+        engine.search(&black, end_instant);
+        assert_eq!(engine.context.evaluator.name(), BlockadeEvaluator.name());
+        assert!(engine.context.tt.probe(white.board.key()).is_none());
+        assert!(engine.context.tt.probe(black.board.key()).is_some());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::bitboard::{step, Bitboard, DIRECTIONS};
+use crate::constants::*;
+use crate::game::{Position, State, Status};
+use crate::rules::game_status;
+use crate::search::{game_over_value, heuristic, heuristic_features};
+use crate::serde::{Serialize, Deserialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Weights of the heuristic. A parameter file is a JSON object with any of the fields,
+// the missing ones keep their default value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalParams {
+    // Per white checker or king more than black
+    pub checker_difference: i32,
+    // Added to the checker difference, 7 evens out the Tablut starting position
+    pub checker_offset: i32,
+    // Per escape the king reaches in one move
+    pub king_escape: i32,
+    // Most escapes the king would reach after one more move
+    pub king_escape_in_one_move: i32,
+    // Per black checker next to the king, subtracted
+    pub black_around_king: i32,
+    // Per black checker diagonal to the king, subtracted
+    pub black_diagonal_to_king: i32,
+    // Values returned at once when the king wins in one or two moves
+    pub win_in_one_move: i32,
+    pub win_in_two_moves: i32,
+    // Blockade evaluator only, subtracted: per black checker next to an escape, per black
+    // checker on an escape, and per direction in which the king runs into black or a camp
+    pub black_guard: i32,
+    pub black_on_escape: i32,
+    pub king_line_closed: i32,
+    // King cell weights of the 9x9 board, other sizes are scaled onto it
+    pub position_weights: [[i32; 9]; 9]
+}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams {
+            checker_difference: 25,
+            checker_offset: 7,
+            king_escape: 70,
+            king_escape_in_one_move: 35,
+            black_around_king: 10,
+            black_diagonal_to_king: 10,
+            win_in_one_move: 10000,
+            win_in_two_moves: 5000,
+            black_guard: 10,
+            black_on_escape: 10,
+            king_line_closed: 15,
+            position_weights: [
+                [0,  0,  0,  0,  0,  0,  0,  0,  0],
+                [0,  5, 10,  0,  0,  0, 10,  5,  0],
+                [0,  5, 10,  5,  5,  5, 10,  5,  0],
+                [0,  0,  0,  0,  0,  0,  0,  0,  0],
+                [0,  0,  5,  0, -10,  0,  5,  0,  0],
+                [0,  0,  0,  0,  0,  0,  0,  0,  0],
+                [0,  5, 10,  5,  5,  5, 10,  5,  0],
+                [0,  5, 10,  0,  0,  0, 10,  5,  0],
+                [0,  0,  0,  0,  0,  0,  0,  0,  0],
+            ]
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn load(path: &Path) -> Result<EvalParams, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).map_err(|e| e.to_string())
+    }
+
+    // Every weight in field order, the position weights row by row
+    pub fn values(&self) -> Vec<i32> {
+        let mut values = vec![
+            self.checker_difference,
+            self.checker_offset,
+            self.king_escape,
+            self.king_escape_in_one_move,
+            self.black_around_king,
+            self.black_diagonal_to_king,
+            self.win_in_one_move,
+            self.win_in_two_moves,
+            self.black_guard,
+            self.black_on_escape,
+            self.king_line_closed
+        ];
+        values.extend(self.position_weights.iter().flatten());
+        values
+    }
+
+    // Inverse of values
+    pub fn from_values(values: &[i32]) -> EvalParams {
+        let mut params = EvalParams {
+            checker_difference: values[0],
+            checker_offset: values[1],
+            king_escape: values[2],
+            king_escape_in_one_move: values[3],
+            black_around_king: values[4],
+            black_diagonal_to_king: values[5],
+            win_in_one_move: values[6],
+            win_in_two_moves: values[7],
+            black_guard: values[8],
+            black_on_escape: values[9],
+            king_line_closed: values[10],
+            ..EvalParams::default()
+        };
+        for (i, value) in values[11..].iter().enumerate() {
+            params.position_weights[i / 9][i % 9] = *value;
+        }
+        params
+    }
+}
+
+// A feature of the position and its weight, the weight is negative for subtracted features
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Term {
+    pub name: &'static str,
+    pub value: i32,
+    pub weight: i32
+}
+
+impl Term {
+    pub fn contribution(&self) -> i32 {
+        self.value * self.weight
+    }
+}
+
+// How an evaluator reached its value
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    pub evaluator: &'static str,
+    pub terms: Vec<Term>,
+    // Game result or shortcut rule that replaces the sum of the terms, with its value
+    pub shortcut: Option<(String, i32)>,
+    pub value: i32
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} evaluation", self.evaluator)?;
+        for term in self.terms.iter() {
+            writeln!(f, "{:<26}{:>5} x {:>5} = {:>6}", term.name, term.value, term.weight, term.contribution())?;
+        }
+        if let Some((rule, value)) = &self.shortcut {
+            writeln!(f, "shortcut: {} = {}", rule, value)?;
+        }
+        write!(f, "value {}", self.value)
+    }
+}
+
+// Evaluation of a position, positive when white is better, i32::MAX and i32::MIN when the
+// game is won by white or black. An engine may use a different one for each side it plays.
+pub trait Evaluator: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn evaluate(&self, state: &State, params: &EvalParams) -> i32;
+    // Terms of the value returned by evaluate
+    fn explain(&self, state: &State, params: &EvalParams) -> Explanation;
+}
+
+impl fmt::Debug for dyn Evaluator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// White's view: material, king escapes and the black checkers around the king
+pub struct EscapeEvaluator;
+
+impl Evaluator for EscapeEvaluator {
+    fn name(&self) -> &'static str {
+        "escape"
+    }
+
+    fn evaluate(&self, state: &State, params: &EvalParams) -> i32 {
+        heuristic(state, params)
+    }
+
+    fn explain(&self, state: &State, params: &EvalParams) -> Explanation {
+        let mut explanation = Explanation { evaluator: self.name(), terms: vec![], shortcut: None, value: 0 };
+        if let Some(value) = game_over_value(state) {
+            let result = match (game_status(state), state.color == WHITE) {
+                (Status::WIN, true) | (Status::LOSS, false) => "white won",
+                (Status::WIN, false) | (Status::LOSS, true) => "black won",
+                _ => "draw"
+            };
+            explanation.shortcut = Some((result.to_string(), value));
+            explanation.value = value;
+            return explanation;
+        }
+        let features = heuristic_features(state);
+        explanation.terms = features.terms(state.board.size(), params).to_vec();
+        explanation.shortcut = features.shortcut.map(|shortcut| (shortcut.rule().to_string(), shortcut.value(params)));
+        explanation.value = features.value(state.board.size(), params);
+        explanation
+    }
+}
+
+// Black's view: the escape evaluation, minus the black checkers that guard the escapes and
+// the directions in which the king can not run to the edge
+pub struct BlockadeEvaluator;
+
+impl Evaluator for BlockadeEvaluator {
+    fn name(&self) -> &'static str {
+        "blockade"
+    }
+
+    fn evaluate(&self, state: &State, params: &EvalParams) -> i32 {
+        let value = heuristic(state, params);
+        if value == i32::MAX || value == i32::MIN {
+            return value;
+        }
+        value + blockade_terms(state, params).iter().map(|term| term.contribution()).sum::<i32>()
+    }
+
+    fn explain(&self, state: &State, params: &EvalParams) -> Explanation {
+        let mut explanation = EscapeEvaluator.explain(state, params);
+        explanation.evaluator = self.name();
+        if explanation.value == i32::MAX || explanation.value == i32::MIN {
+            return explanation;
+        }
+        let terms = blockade_terms(state, params);
+        explanation.value += terms.iter().map(|term| term.contribution()).sum::<i32>();
+        explanation.terms.extend(terms.iter());
+        explanation
+    }
+}
+
+fn blockade_terms(state: &State, params: &EvalParams) -> [Term; 3] {
+    let board = &state.board;
+    let rules = board.rules();
+    let black = board.black();
+    let guards = rules.escapes.neighbours() & rules.area & !(rules.escapes | rules.camps | rules.throne);
+    let closed = board.king_cell().map_or(0, |king| closed_king_lines(state, king));
+    [
+        Term { name: "black guards", value: (black & guards).count() as i32, weight: -params.black_guard },
+        Term { name: "black on escapes", value: (black & rules.escapes).count() as i32, weight: -params.black_on_escape },
+        Term { name: "closed king lines", value: closed as i32, weight: -params.king_line_closed }
+    ]
+}
+
+// Directions in which the first checker or barrier the king meets is black or a camp
+fn closed_king_lines(state: &State, king: Position) -> u32 {
+    let board = &state.board;
+    let rules = board.rules();
+    let stops: Bitboard = board.occupied() | rules.blocked(false);
+    let closing: Bitboard = board.black() | rules.camps;
+    DIRECTIONS.iter().filter(|direction| {
+        let mut cell = step(king, **direction);
+        while let Some(c) = cell.filter(|c| !stops.contains(*c)) {
+            cell = step(c, **direction);
+        }
+        cell.is_some_and(|c| closing.contains(c))
+    }).count() as u32
+}
+
+pub const EVALUATORS: [&dyn Evaluator; 2] = [&EscapeEvaluator, &BlockadeEvaluator];
+
+pub fn evaluator_by_name(name: &str) -> Option<&'static dyn Evaluator> {
+    EVALUATORS.iter().find(|evaluator| evaluator.name() == name).copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Board;
+    use crate::record::escape_game;
+    use crate::ruleset::ASHTON;
+
+    #[test]
+    fn test_eval_params() {
+        let params: EvalParams = serde_json::from_str("{\"king_escape\": 80}").unwrap();
+        assert_eq!(params, EvalParams { king_escape: 80, ..EvalParams::default() });
+        assert!(serde_json::from_str::<EvalParams>("{\"king_escapes\": 80}").is_err());
+        assert!(serde_json::from_str::<EvalParams>("{\"position_weights\": [[0]]}").is_err());
+
+        let path = std::env::temp_dir().join(format!("muscovite_params_{}.json", std::process::id()));
+        params.save(&path).unwrap();
+        assert_eq!(EvalParams::load(&path).unwrap(), params);
+        fs::remove_file(&path).unwrap();
+        assert!(EvalParams::load(&path).is_err());
+
+        let values = params.values();
+        assert_eq!(values.len(), 11 + 81);
+        assert_eq!(values[2], 80);
+        assert_eq!(values[11 + 4 * 9 + 4], -10);
+        assert_eq!(EvalParams::from_values(&values), params);
+    }
+
+    #[test]
+    fn test_evaluators() {
+        assert_eq!(evaluator_by_name("blockade").unwrap().name(), "blockade");
+        assert!(evaluator_by_name("material").is_none());
+        let params = EvalParams::default();
+
+        // Nothing guards the escapes at the start
+        let mut state = State::init(WHITE.to_string());
+        state.make_move(&"e3->h3".parse().unwrap());
+        assert_eq!(BlockadeEvaluator.evaluate(&state, &params), EscapeEvaluator.evaluate(&state, &params));
+
+        // One black checker next to an escape, the king runs into black up, down and right
+        // and into the throne on the left
+        state.board = Board::new([
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+            [0, 0, 2, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [2, 2, 0, 0, 0, 3, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+        ]);
+        state.history.push(state.board);
+        state.history_keys.push(state.board.key());
+        assert_eq!(closed_king_lines(&state, Position { x: 5, y: 4 }), 3);
+        let escape = EscapeEvaluator.evaluate(&state, &params);
+        assert_eq!(BlockadeEvaluator.evaluate(&state, &params), escape - params.black_guard - 3 * params.king_line_closed);
+    }
+
+    #[test]
+    fn test_explain() {
+        let params = EvalParams::default();
+        let mut state = State::init(WHITE.to_string());
+        let explanation = EscapeEvaluator.explain(&state, &params);
+        assert_eq!(explanation.terms.len(), 7);
+        assert_eq!(explanation.shortcut, None);
+        assert_eq!(explanation.terms.iter().map(|term| term.contribution()).sum::<i32>(), explanation.value);
+
+        // Every position of a game where the king escapes, the last one is over
+        for m in escape_game().moves.iter() {
+            state.make_move(m);
+            for evaluator in EVALUATORS.iter() {
+                let explanation = evaluator.explain(&state, &params);
+                assert_eq!(explanation.evaluator, evaluator.name());
+                assert_eq!(explanation.value, evaluator.evaluate(&state, &params));
+                if explanation.shortcut.is_none() {
+                    assert_eq!(explanation.terms.iter().map(|term| term.contribution()).sum::<i32>(), explanation.value);
+                }
+            }
+        }
+        let explanation = BlockadeEvaluator.explain(&state, &params);
+        assert_eq!(explanation.shortcut, Some(("white won".to_string(), i32::MAX)));
+        assert!(explanation.terms.is_empty());
+
+        // The king on e3 sees the escapes a3 and i3
+        let state = State::from_notation("3BBB3/4B4/4K4/B7B/BB5BB/B7B/9/4B4/3BBB3 b", &ASHTON).unwrap();
+        let explanation = EscapeEvaluator.explain(&state, &params);
+        assert_eq!(explanation.shortcut, Some(("king sees two escapes".to_string(), params.win_in_one_move)));
+        assert_eq!(explanation.value, params.win_in_one_move);
+        assert!(explanation.to_string().contains("shortcut: king sees two escapes = 10000"));
+    }
+}
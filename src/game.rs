@@ -1,7 +1,11 @@
 use crate::constants::*;
-use crate::rules::captures;
+use crate::bitboard::{Bitboard, cell_index, MAX_SIZE, MIN_SIZE};
+use crate::ruleset::{Ruleset, ASHTON};
+use crate::rules::capture_mask;
+use crate::zobrist::{bitboard_key, WHITE_KEYS, BLACK_KEYS, KING_KEYS, BLACK_TO_MOVE_KEY};
 use std::fmt;
 use std::cmp::Eq;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug, Eq)]
 pub struct Position {
@@ -34,42 +38,324 @@ impl fmt::Display for Move {
     }
 }
 
+// Parses a cell such as e5, column letter then row number
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Position, String> {
+        let mut chars = s.chars();
+        let column = chars.next().ok_or("empty cell")?;
+        let x = BOARD_COLUMNS.iter().position(|c| *c == column.to_ascii_lowercase())
+            .ok_or(format!("unknown column {}", column))?;
+        let row: u32 = chars.as_str().parse().map_err(|_| format!("unknown row in {}", s))?;
+        if row == 0 || row > MAX_SIZE {
+            return Err(format!("unknown row in {}", s));
+        }
+        Ok(Position { x: x as u32, y: row - 1 })
+    }
+}
+
+// Parses the notation of Move's Display, such as e5->e7
+impl FromStr for Move {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Move, String> {
+        let mut cells = s.trim().split("->");
+        let from: Position = cells.next().unwrap().parse()?;
+        let to: Position = cells.next().ok_or(format!("missing -> in {}", s))?.parse()?;
+        if cells.next().is_some() {
+            return Err(format!("too many cells in {}", s));
+        }
+        Ok(Move { from, to })
+    }
+}
+
+// Checkers removed from the board by a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Captured {
+    pub white: Bitboard,
+    pub black: Bitboard,
+    pub king: Bitboard
+}
+
 #[derive(Debug, Clone, Copy, Eq)]
 pub struct Board {
-    board: [[u32; 9]; 9]
+    white: Bitboard,
+    black: Bitboard,
+    king: Bitboard,
+    // Zobrist key of the checkers and of the side to move
+    key: u64,
+    rules: &'static Ruleset
 }
 
 impl Board {
+    #[allow(dead_code)]
     pub fn init() -> Board {
-        Board {
-            board: INITIAL_BOARD
-        }
+        Board::with_rules(&ASHTON)
+    }
+
+    // Starting position of a variant, with the side that moves first to move
+    pub fn with_rules(rules: &'static Ruleset) -> Board {
+        assert!(rules.size >= MIN_SIZE && rules.size <= MAX_SIZE, "{}x{} boards are not supported", rules.size, rules.size);
+        let mut board = Board {
+            white: Bitboard::from_layout(rules.initial, b'W'),
+            black: Bitboard::from_layout(rules.initial, b'B'),
+            king: Bitboard::from_layout(rules.initial, b'K'),
+            key: 0,
+            rules
+        };
+        board.set_turn(rules.first_turn);
+        board
     }
 
+    // Ashton rules, white is to move on the new board, see Board::set_turn
+    #[allow(dead_code)]
     pub fn new(board: [[u32; 9]; 9]) -> Board {
-       Board {
-           board
-       }
+        Board::from_cells(&ASHTON, &board)
+    }
+
+    // Checkers given as cell contents, one row per board row, white to move
+    pub fn from_cells<R: AsRef<[u32]>>(rules: &'static Ruleset, rows: &[R]) -> Board {
+        let mut white = Bitboard::EMPTY;
+        let mut black = Bitboard::EMPTY;
+        let mut king = Bitboard::EMPTY;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.as_ref().iter().enumerate() {
+                let p = Position { x: x as u32, y: y as u32 };
+                match *cell {
+                    W => white.set(p),
+                    B => black.set(p),
+                    K => king.set(p),
+                    _ => {}
+                }
+            }
+        }
+        let mut board = Board {
+            white: white & rules.area,
+            black: black & rules.area,
+            king: king & rules.area,
+            key: 0,
+            rules
+        };
+        board.set_turn(WHITE);
+        board
+    }
+
+    // Parses a position written one rank per board row from the top, separated by '/', with
+    // W, B and K for checkers and numbers for runs of empty cells, optionally followed by w or
+    // b for the side to move, white by default: "3BBB3/4B4/4W4/B3W3B/BBWWKWWBB/B3W3B/4W4/4B4/3BBB3 w"
+    pub fn from_notation(notation: &str, rules: &'static Ruleset) -> Result<Board, String> {
+        let mut fields = notation.split_whitespace();
+        let placement = fields.next().ok_or("empty position")?;
+        let turn = parse_turn(fields.next())?;
+        if let Some(extra) = fields.next() {
+            return Err(format!("unexpected {} after the side to move", extra));
+        }
+
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != rules.size as usize {
+            return Err(format!("{} ranks given, {} expects {}", ranks.len(), rules.name, rules.size));
+        }
+        let mut rows: Vec<Vec<u32>> = Vec::with_capacity(ranks.len());
+        for rank in ranks.iter() {
+            let mut row: Vec<u32> = vec![];
+            let mut empty: usize = 0;
+            for c in rank.chars() {
+                if let Some(digit) = c.to_digit(10) {
+                    empty = empty * 10 + digit as usize;
+                    if row.len() + empty > rules.size as usize {
+                        return Err(format!("rank {} has more than {} cells", rank, rules.size));
+                    }
+                    continue;
+                }
+                row.resize(row.len() + empty, E);
+                empty = 0;
+                match c {
+                    'W' => row.push(W),
+                    'B' => row.push(B),
+                    'K' => row.push(K),
+                    _ => return Err(format!("unknown symbol {} in rank {}", c, rank))
+                }
+            }
+            row.resize(row.len() + empty, E);
+            if row.len() != rules.size as usize {
+                return Err(format!("rank {} has {} cells instead of {}", rank, row.len(), rules.size));
+            }
+            rows.push(row);
+        }
+
+        let mut board = Board::from_cells(rules, &rows);
+        if board.king.count() != 1 {
+            return Err(format!("{} kings on the board", board.king.count()));
+        }
+        board.set_turn(turn);
+        Ok(board)
+    }
+
+    // Checkers in the notation of Board::from_notation, without the side to move
+    pub fn to_notation(self) -> String {
+        let mut ranks: Vec<String> = Vec::with_capacity(self.size() as usize);
+        for y in 0..self.size() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for x in 0..self.size() {
+                let symbol = match self.cell_content(Position { x, y }) {
+                    W => 'W',
+                    B => 'B',
+                    K => 'K',
+                    _ => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                rank.push(symbol);
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+        ranks.join("/")
+    }
+
+    // Recomputes the key from scratch for the given side to move
+    pub fn set_turn(&mut self, turn: &str) {
+        self.key = bitboard_key(self.white, &WHITE_KEYS) ^
+            bitboard_key(self.black, &BLACK_KEYS) ^
+            bitboard_key(self.king, &KING_KEYS);
+        if turn == BLACK {
+            self.key ^= BLACK_TO_MOVE_KEY;
+        }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn rules(&self) -> &'static Ruleset {
+        self.rules
+    }
+
+    // Number of rows and columns
+    pub fn size(&self) -> u32 {
+        self.rules.size
+    }
+
+    // Plays the same checkers under another variant of the same size
+    #[allow(dead_code)]
+    pub fn set_rules(&mut self, rules: &'static Ruleset) {
+        assert_eq!(rules.size, self.rules.size, "{} is played on a {}x{} board", rules.name, rules.size, rules.size);
+        self.rules = rules;
     }
 
     pub fn apply_move(&mut self, m: &Move) {
-        let cell_content: u32 = self.cell_content(m.from);
-        self.board[m.to.y as usize][m.to.x as usize] = cell_content;
-        self.board[m.from.y as usize][m.from.x as usize] = E;
-        let checkers_captured = captures(&self, m);
-        for checker in checkers_captured {
-            self.board[checker.y as usize][checker.x as usize] = E;
+        self.make_move(m);
+    }
+
+    // Applies a move and returns what it captured, so that it can be undone
+    pub fn make_move(&mut self, m: &Move) -> Captured {
+        self.move_checker(m);
+        let mask = capture_mask(self, m);
+        let captured = Captured {
+            white: self.white & mask,
+            black: self.black & mask,
+            king: self.king & mask
+        };
+        self.key ^= bitboard_key(captured.white, &WHITE_KEYS) ^
+            bitboard_key(captured.black, &BLACK_KEYS) ^
+            bitboard_key(captured.king, &KING_KEYS);
+        self.white &= !mask;
+        self.black &= !mask;
+        self.king &= !mask;
+        self.key ^= BLACK_TO_MOVE_KEY;
+        captured
+    }
+
+    // Reverts a move applied with Board::make_move
+    pub fn unmake_move(&mut self, m: &Move, captured: Captured) {
+        self.key ^= BLACK_TO_MOVE_KEY;
+        self.white |= captured.white;
+        self.black |= captured.black;
+        self.king |= captured.king;
+        self.key ^= bitboard_key(captured.white, &WHITE_KEYS) ^
+            bitboard_key(captured.black, &BLACK_KEYS) ^
+            bitboard_key(captured.king, &KING_KEYS);
+        self.move_checker(&Move { from: m.to, to: m.from });
+    }
+
+    // Passes the turn without moving, as in a null move
+    pub fn pass(&mut self) {
+        self.key ^= BLACK_TO_MOVE_KEY;
+    }
+
+    // Moves a checker without resolving captures or changing the side to move
+    pub fn move_checker(&mut self, m: &Move) {
+        let from = Bitboard::from_position(m.from);
+        let to = Bitboard::from_position(m.to);
+        let (from_index, to_index) = (cell_index(m.from), cell_index(m.to));
+        if !(self.white & from).is_empty() {
+            self.white ^= from | to;
+            self.key ^= WHITE_KEYS[from_index] ^ WHITE_KEYS[to_index];
+        } else if !(self.black & from).is_empty() {
+            self.black ^= from | to;
+            self.key ^= BLACK_KEYS[from_index] ^ BLACK_KEYS[to_index];
+        } else if !(self.king & from).is_empty() {
+            self.king ^= from | to;
+            self.key ^= KING_KEYS[from_index] ^ KING_KEYS[to_index];
         }
     }
 
+    // White checkers, king excluded
+    pub fn white(&self) -> Bitboard {
+        self.white
+    }
+
+    pub fn black(&self) -> Bitboard {
+        self.black
+    }
+
+    pub fn king(&self) -> Bitboard {
+        self.king
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.white | self.black | self.king
+    }
+
+    // Empty hostile cells: throne, blocking camps and, in some variants, escapes
+    pub fn barriers(&self) -> Bitboard {
+        self.rules.hostile() & !self.occupied()
+    }
+
     pub fn cell_type(&self, p: Position) -> u32 {
-        BOARD[p.y as usize][p.x as usize]
+        if self.rules.camps.contains(p) {
+            C
+        } else if self.rules.throne.contains(p) {
+            T
+        } else if self.rules.escapes.contains(p) {
+            F
+        } else {
+            R
+        }
     }
 
     pub fn cell_content(&self, p: Position) -> u32 {
-        self.board[p.y as usize][p.x as usize]
+        if self.white.contains(p) {
+            W
+        } else if self.black.contains(p) {
+            B
+        } else if self.king.contains(p) {
+            K
+        } else {
+            E
+        }
     }
 
+    #[allow(dead_code)]
     pub fn cell_color(&self, p: Position) -> Option<String> {
         let content: u32 = self.cell_content(p);
         if content == W || content == K {
@@ -83,6 +369,7 @@ impl Board {
 
     pub fn surrounding_cells(&self, p: Position) -> [Option<Position>; 4] {
         // Up Down Right Left
+        let last = self.size() - 1;
         let mut s: [Option<Position>; 4] = [None, None, None, None];
 
         // Up
@@ -90,11 +377,11 @@ impl Board {
             s[0] = Some(Position { x: p.x, y: p.y-1 });
         }
         // Down
-        if p.y < 8 {
+        if p.y < last {
             s[1] = Some(Position { x: p.x, y: p.y+1 });
         }
         // Right
-        if p.x < 8 {
+        if p.x < last {
             s[2] = Some(Position { x: p.x+1, y: p.y });
         }
         // Left
@@ -106,9 +393,10 @@ impl Board {
 
     pub fn surrounding_diagonal_cells(&self, p: Position) -> [Option<Position>; 4] {
         let mut s: [Option<Position>; 4] = [None, None, None, None];
+        let last = self.size() - 1;
 
         // Up Right
-        if p.y > 0 && p.x < 8 {
+        if p.y > 0 && p.x < last {
             s[0] = Some(Position { x: p.x+1, y: p.y-1 });
         }
         // Up Left
@@ -116,11 +404,11 @@ impl Board {
             s[1] = Some(Position { x: p.x-1, y: p.y-1 });
         }
         // Down Right
-        if p.y < 8 && p.x < 8 {
+        if p.y < last && p.x < last {
             s[2] = Some(Position { x: p.x+1, y: p.y+1 });
         }
         // Down Left
-        if p.y < 8 && p.x > 0 {
+        if p.y < last && p.x > 0 {
             s[3] = Some(Position { x: p.x-1, y: p.y+1 });
         }
 
@@ -128,7 +416,7 @@ impl Board {
     }
 
     pub fn upper_cell(&self, p: Position) -> Option<Position> {
-        if p.x > 8 || p.y > 8 || p.y == 0 {
+        if p.x >= self.size() || p.y >= self.size() || p.y == 0 {
             None
         } else {
             Some(Position { x: p.x, y: p.y-1 })
@@ -136,7 +424,7 @@ impl Board {
     }
 
     pub fn lower_cell(&self, p: Position) -> Option<Position> {
-        if p.x > 8 || p.y > 8 || p.y == 8 {
+        if p.x >= self.size() || p.y >= self.size() || p.y == self.size() - 1 {
             None
         } else {
             Some(Position { x: p.x, y: p.y+1 })
@@ -144,7 +432,7 @@ impl Board {
     }
 
     pub fn right_cell(&self, p: Position) -> Option<Position> {
-        if p.x > 8 || p.y > 8 || p.x == 8 {
+        if p.x >= self.size() || p.y >= self.size() || p.x == self.size() - 1 {
             None
         } else {
             Some(Position { x: p.x+1, y: p.y })
@@ -152,104 +440,91 @@ impl Board {
     }
 
     pub fn left_cell(&self, p: Position) -> Option<Position> {
-        if p.x > 8 || p.y > 8 || p.x == 0 {
+        if p.x >= self.size() || p.y >= self.size() || p.x == 0 {
             None
         } else {
             Some(Position { x: p.x-1, y: p.y })
         }
     }
 
+    #[allow(dead_code)]
     pub fn filter_cells(&self, cell_content: u32) -> Vec<Position> {
-        let mut cells: Vec<Position> = vec![];
-        for (y, row) in self.board.iter().enumerate() {
-            // println!("{:?}", row);
-            for (x, cell) in row.iter().enumerate() {
-                if cell == &cell_content {
-                    let position = Position {
-                        x: x as u32,
-                        y: y as u32
-                    };
-                    // println!("{} {:?}", cell, position);
-                    cells.push(position);
-                }
-            }
-        }
-        cells
+        let cells = match cell_content {
+            W => self.white,
+            B => self.black,
+            K => self.king,
+            _ => self.rules.area & !self.occupied()
+        };
+        cells.iter().collect()
     }
 
     pub fn king_cell(&self) -> Option<Position> {
-        self.filter_cells(K).first().copied()
+        self.king.first()
     }
 
+    #[allow(dead_code)]
     pub fn white_cells(&self) -> Vec<Position> {
         let mut cells = self.filter_cells(W);
         cells.push(self.king_cell().unwrap());
         cells
     }
 
+    #[allow(dead_code)]
     pub fn black_cells(&self) -> Vec<Position> {
         self.filter_cells(B)
     }
 
     pub fn is_empty(&self, p: Position) -> bool {
-        self.cell_content(p) == E
+        !self.occupied().contains(p)
     }
 
     pub fn is_king_in_throne(&self) -> bool {
-        let king = self.king_cell();
-        if king.is_none() {
-            false
-        } else {
-            self.cell_type(king.unwrap()) == T
-        }
-
+        !(self.king & self.rules.throne).is_empty()
     }
 
     pub fn is_king_next_throne(&self) -> bool {
-        let king = self.king_cell();
-        if king.is_none() {
-            return false;
-        }
-        self.surrounding_cells(king.unwrap()).iter()
-            .any(|cell| cell.is_some() && self.cell_type(cell.unwrap()) == T)
+        !(self.king.neighbours() & self.rules.throne).is_empty()
     }
 
 }
 
+// Boards are equal when they hold the same checkers, whoever is to move
 impl PartialEq for Board {
     fn eq(&self, other: &Self) -> bool {
-        self.board == other.board
+        self.white == other.white && self.black == other.black && self.king == other.king
     }
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut out: String = String::from("");
-        out.push_str("    a   b   c   d   e   f   g   h   i\n");
-        out.push_str("  ┌───┬───┬───┬───┬───┬───┬───┬───┬───┐\n");
-        for (y, row) in self.board.iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
+        let size = self.size();
+        let columns: Vec<String> = BOARD_COLUMNS[..size as usize].iter().map(|c| c.to_string()).collect();
+        let mut out: String = format!("     {}\n", columns.join("   "));
+        out.push_str(&format!("   ┌{}───┐\n", "───┬".repeat(size as usize - 1)));
+        for y in 0..size {
+            for x in 0..size {
                 if x == 0 {
-                   out.push_str(&format!("{} ", y+1));
+                   out.push_str(&format!("{:>2} ", y+1));
                 }
-                if cell == &W {
+                let cell = self.cell_content(Position { x, y });
+                if cell == W {
                     out.push_str("│ ○ ");
-                } else if cell == &B {
+                } else if cell == B {
                     out.push_str("│ ● ");
-                } else if cell == &K {
+                } else if cell == K {
                     out.push_str("│ △ ");
                 } else {
                     out.push_str("│   ");
                 }
-                if x == 8 {
+                if x == size - 1 {
                     out.push_str("│\n")
                 }
             }
-            if y < 8 {
-                out.push_str("  ├───┼───┼───┼───┼───┼───┼───┼───┼───┤\n");
+            if y < size - 1 {
+                out.push_str(&format!("   ├{}───┤\n", "───┼".repeat(size as usize - 1)));
             }
         }
-        out.push_str("  └───┴───┴───┴───┴───┴───┴───┴───┴───┘");
+        out.push_str(&format!("   └{}───┘", "───┴".repeat(size as usize - 1)));
         write!(f, "{}", out)
     }
 }
@@ -268,29 +543,134 @@ pub struct State {
     pub board: Board,
     pub turn: String,
     pub history: Vec<Board>,
+    pub history_keys: Vec<u64>,
     pub status: Status,
 }
 
 impl State {
+    #[allow(dead_code)]
     pub fn init(color: String) -> State {
+        State::with_rules(color, &ASHTON)
+    }
+
+    // Starting position of a variant, with the side that moves first to move
+    pub fn with_rules(color: String, rules: &'static Ruleset) -> State {
+        let board = Board::with_rules(rules);
         State {
             color,
-            board: Board::init(),
-            turn: WHITE.to_string(),
-            history: vec![Board::init()],
+            board,
+            turn: rules.first_turn.to_string(),
+            history: vec![board],
+            history_keys: vec![board.key()],
             status: Status::ONGOING
         }
     }
 
+    // Position in the notation of Board::from_notation, the side to move plays next
+    pub fn from_notation(notation: &str, rules: &'static Ruleset) -> Result<State, String> {
+        let board = Board::from_notation(notation, rules)?;
+        let turn = parse_turn(notation.split_whitespace().nth(1))?;
+        Ok(State {
+            color: turn.to_string(),
+            board,
+            turn: turn.to_string(),
+            history: vec![board],
+            history_keys: vec![board.key()],
+            status: Status::ONGOING
+        })
+    }
+
+    pub fn to_notation(&self) -> String {
+        format!("{} {}", self.board.to_notation(), if self.turn == WHITE { 'w' } else { 'b' })
+    }
+
     pub fn apply_move(&mut self, m: &Move) {
         self.history.push(self.board);
+        self.history_keys.push(self.board.key());
         self.board.apply_move(&m);
     }
+
+    // Applies a move and passes the turn, returning what is needed to undo it
+    pub fn make_move(&mut self, m: &Move) -> Undo {
+        let undo = Undo {
+            m: Some(*m),
+            captured: Captured { white: Bitboard::EMPTY, black: Bitboard::EMPTY, king: Bitboard::EMPTY },
+            color: if self.color == WHITE { WHITE } else { BLACK },
+            turn: if self.turn == WHITE { WHITE } else { BLACK },
+            history_len: self.history.len()
+        };
+        self.history.push(self.board);
+        self.history_keys.push(self.board.key());
+        let captured = self.board.make_move(m);
+        switch_color(&mut self.color);
+        switch_color(&mut self.turn);
+        Undo { captured, ..undo }
+    }
+
+    // Passes the turn to the opponent without moving, for null-move pruning
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = Undo {
+            m: None,
+            captured: Captured { white: Bitboard::EMPTY, black: Bitboard::EMPTY, king: Bitboard::EMPTY },
+            color: if self.color == WHITE { WHITE } else { BLACK },
+            turn: if self.turn == WHITE { WHITE } else { BLACK },
+            history_len: self.history.len()
+        };
+        self.history.push(self.board);
+        self.history_keys.push(self.board.key());
+        self.board.pass();
+        switch_color(&mut self.color);
+        switch_color(&mut self.turn);
+        undo
+    }
+
+    // Restores the state as it was before State::make_move or State::make_null_move
+    pub fn unmake_move(&mut self, undo: Undo) {
+        match undo.m {
+            Some(m) => self.board.unmake_move(&m, undo.captured),
+            None => self.board.pass()
+        }
+        self.history.truncate(undo.history_len);
+        self.history_keys.truncate(undo.history_len);
+        self.color.clear();
+        self.color.push_str(undo.color);
+        self.turn.clear();
+        self.turn.push_str(undo.turn);
+    }
+}
+
+// Undo record of State::make_move
+#[derive(Debug, Clone, Copy)]
+pub struct Undo {
+    // None for a null move
+    m: Option<Move>,
+    captured: Captured,
+    color: &'static str,
+    turn: &'static str,
+    // Length of the history before the move
+    history_len: usize
+}
+
+// Side to move of a position notation, white when it is missing
+fn parse_turn(field: Option<&str>) -> Result<&'static str, String> {
+    match field {
+        None | Some("w") => Ok(WHITE),
+        Some("b") => Ok(BLACK),
+        Some(other) => Err(format!("unknown side to move {}, expected w or b", other))
+    }
+}
+
+// Switches white and black in place, reusing the string buffer
+fn switch_color(color: &mut String) {
+    let opposite = if color == WHITE { BLACK } else { WHITE };
+    color.clear();
+    color.push_str(opposite);
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::ruleset::HNEFATAFL;
 
     #[test]
     fn test_board_cell_content() {
@@ -339,4 +719,151 @@ mod test {
         assert!(board.is_empty(Position { x: 5, y: 2}));
         assert_eq!(board.cell_content(Position { x: 5, y: 3 }), B);
     }
+
+    #[test]
+    fn test_board_key() {
+        let mut board = Board::new([
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 1, 2, 1, 2, 0],
+            [2, 0, 0, 0, 1, 0, 0, 0, 0],
+            [2, 2, 1, 1, 3, 1, 0, 2, 2],
+            [2, 0, 0, 0, 1, 0, 0, 0, 2],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        let initial_key = board.key();
+
+        // Double capture
+        board.apply_move(&Move {
+            from: Position { x: 5, y: 2 },
+            to: Position { x: 5, y: 1 }
+        });
+        board.apply_move(&Move {
+            from: Position { x: 5, y: 1 },
+            to: Position { x: 5, y: 2 }
+        });
+        assert!(board.is_empty(Position { x: 4, y: 2 }));
+        assert!(board.is_empty(Position { x: 6, y: 2 }));
+        let mut expected = board;
+        expected.set_turn(WHITE);
+        assert_eq!(board.key(), expected.key());
+        assert_ne!(board.key(), initial_key);
+
+        // Side to move
+        let mut black_to_move = board;
+        black_to_move.set_turn(BLACK);
+        assert_eq!(black_to_move, board);
+        assert_ne!(black_to_move.key(), board.key());
+    }
+
+    #[test]
+    fn test_move_notation() {
+        let m = Move { from: Position { x: 4, y: 4 }, to: Position { x: 4, y: 6 } };
+        assert_eq!(m.to_string(), "e5->e7");
+        assert_eq!("e5->e7".parse::<Move>(), Ok(m));
+        assert_eq!("k11->a11".parse::<Move>().unwrap().to_string(), "k11->a11");
+        assert!("e5".parse::<Move>().is_err());
+        assert!("e5->z7".parse::<Move>().is_err());
+        assert!("e0->e7".parse::<Move>().is_err());
+        assert!("e5->e7->e9".parse::<Move>().is_err());
+    }
+
+    #[test]
+    fn test_notation() {
+        let state = State::init(WHITE.to_string());
+        assert_eq!(state.to_notation(), "3BBB3/4B4/4W4/B3W3B/BBWWKWWBB/B3W3B/4W4/4B4/3BBB3 w");
+        let parsed = State::from_notation(&state.to_notation(), &ASHTON).unwrap();
+        assert_eq!(parsed.board, state.board);
+        assert_eq!(parsed.board.key(), state.board.key());
+        assert_eq!(parsed.turn, WHITE);
+
+        // Round trip of a position with black to move
+        let mut board = Board::new([
+            [0, 0, 0, 0, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 1, 0, 1, 2, 0],
+            [2, 0, 0, 0, 1, 0, 0, 0, 0],
+            [2, 2, 1, 1, 3, 1, 0, 2, 2],
+            [2, 0, 0, 0, 1, 0, 0, 0, 2],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        board.set_turn(BLACK);
+        let notation = format!("{} b", board.to_notation());
+        assert_eq!(notation, "4BB3/4B4/3BW1WB1/B3W4/BBWWKW1BB/B3W3B/4W4/4B4/3BBB3 b");
+        let state = State::from_notation(&notation, &ASHTON).unwrap();
+        assert_eq!(state.board, board);
+        assert_eq!(state.board.key(), board.key());
+        assert_eq!(state.color, BLACK);
+        assert_eq!(state.to_notation(), notation);
+
+        // Runs of ten or more empty cells
+        let mut rows = vec![vec![0u32; 11]; 11];
+        rows[10][4] = K;
+        let board = Board::from_cells(&HNEFATAFL, &rows);
+        assert!(board.to_notation().starts_with("11/11/"));
+        assert_eq!(Board::from_notation(&board.to_notation(), &HNEFATAFL).unwrap(), board);
+        let start = State::with_rules(BLACK.to_string(), &HNEFATAFL);
+        assert_eq!((start.turn.as_str(), start.to_notation().ends_with(" b")), (BLACK, true));
+        assert_eq!(State::from_notation(&start.to_notation(), &HNEFATAFL).unwrap().board, start.board);
+
+        assert!(Board::from_notation("", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K4/9/9/9", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K5/9/9/9/9", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K4/9/9/9/99999999999999999999", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K4/9/9/9/4294967296", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4X4/9/9/9/9", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/9/9/9/9/9", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K4/9/9/9/9 x", &ASHTON).is_err());
+        assert!(Board::from_notation("9/9/9/9/4K4/9/9/9/9 b", &ASHTON).is_ok());
+    }
+
+    #[test]
+    fn test_state_make_unmake_move() {
+        let mut state = State::init(BLACK.to_string());
+        state.turn = BLACK.to_string();
+        state.board = Board::new([
+            [0, 0, 0, 0, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 1, 0, 1, 2, 0],
+            [2, 0, 0, 0, 1, 0, 0, 0, 0],
+            [2, 2, 1, 1, 3, 1, 0, 2, 2],
+            [2, 0, 0, 0, 1, 0, 0, 0, 2],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        state.board.set_turn(BLACK);
+        let before = state.clone();
+
+        // Double capture
+        let m = Move { from: Position { x: 5, y: 0 }, to: Position { x: 5, y: 2 } };
+        let undo = state.make_move(&m);
+        assert!(state.board.is_empty(Position { x: 4, y: 2 }));
+        assert!(state.board.is_empty(Position { x: 6, y: 2 }));
+        assert_eq!(state.color, WHITE);
+        assert_eq!(state.turn, WHITE);
+        assert_eq!(state.history.len(), before.history.len() + 1);
+
+        state.unmake_move(undo);
+        assert_eq!(state.board, before.board);
+        assert_eq!(state.board.key(), before.board.key());
+        assert_eq!(state.color, BLACK);
+        assert_eq!(state.turn, BLACK);
+        assert_eq!(state.history, before.history);
+        assert_eq!(state.history_keys, before.history_keys);
+
+        // Null move
+        let undo = state.make_null_move();
+        assert_eq!(state.board, before.board);
+        assert_ne!(state.board.key(), before.board.key());
+        assert_eq!(state.color, WHITE);
+        state.unmake_move(undo);
+        assert_eq!(state.board.key(), before.board.key());
+        assert_eq!(state.color, BLACK);
+        assert_eq!(state.history_keys, before.history_keys);
+    }
 }
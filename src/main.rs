@@ -8,26 +8,51 @@ extern crate log4rs;
 extern crate chrono;
 
 mod network;
+mod bitboard;
+mod zobrist;
+mod transposition;
 mod constants;
 mod game;
 mod rules;
+mod ruleset;
 mod player;
 mod search;
+mod eval;
+mod mcts;
+mod engine;
 mod serialization;
+mod record;
+mod server;
+mod arena;
+mod tuner;
+mod play;
+mod analysis;
 mod logging;
 
 use constants::*;
 use player::Player;
+use engine::{Engine, EngineConfig, EngineKind};
+use game::State;
+use record::{outcome, GameRecord};
+use server::Server;
+use arena::{run_arena, ArenaConfig, Contender, Sprt};
+use tuner::{load_samples, fit_scale, tune};
+use mcts::Playout;
+use ruleset::{Ruleset, RULESETS, ASHTON};
+use eval::{evaluator_by_name, EvalParams, Evaluator, EscapeEvaluator, EVALUATORS};
 use logging::config_logs;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use std::error::Error;
 use chrono::Local;
 use log::info;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("Muscovite")
         .version("0.1")
         .about("A Tablut Engine")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("color")
             .help("Color of the player, black or white.")
             .required(true)
@@ -54,8 +79,220 @@ fn main() -> Result<(), Box<dyn Error>> {
             .long("timeout")
             .help("Timeout for move")
             .takes_value(true))
+        .args(&engine_args())
+        .subcommand(SubCommand::with_name("analyze")
+            .about("Searches a position, or every position of a game, and prints the best line")
+            .arg(Arg::with_name("position")
+                .long("position")
+                .help("Position in text notation, the initial position if missing")
+                .conflicts_with("record")
+                .takes_value(true))
+            .arg(Arg::with_name("record")
+                .long("record")
+                .help("Game record to review, flagging the moves that lose the most")
+                .takes_value(true))
+            .arg(Arg::with_name("threshold")
+                .long("threshold")
+                .help("Evaluation a move must lose to be flagged as a blunder")
+                .takes_value(true))
+            .arg(Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .help("Maximum search depth")
+                .takes_value(true))
+            .arg(Arg::with_name("time")
+                .long("time")
+                .help("Search time in seconds, for each position of a game")
+                .default_value("10")
+                .takes_value(true))
+            .args(&engine_args()))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Runs a local game server for two players")
+            .arg(Arg::with_name("address")
+                .short("a")
+                .long("address")
+                .default_value("localhost")
+                .help("Address to listen on")
+                .takes_value(true))
+            .arg(Arg::with_name("white-port")
+                .long("white-port")
+                .help("Port of the white player")
+                .takes_value(true))
+            .arg(Arg::with_name("black-port")
+                .long("black-port")
+                .help("Port of the black player")
+                .takes_value(true))
+            .arg(Arg::with_name("timeout")
+                .short("t")
+                .long("timeout")
+                .help("Seconds a player may take for a move")
+                .default_value("60")
+                .takes_value(true))
+            .arg(rules_arg()))
+        .subcommand(SubCommand::with_name("replay")
+            .about("Prints every position of a saved game")
+            .arg(Arg::with_name("record")
+                .help("Game record file")
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("arena")
+            .about("Plays games between two engine configurations and estimates their Elo difference")
+            .arg(Arg::with_name("first")
+                .help("First engine, comma separated options such as alphabeta,depth=4,time=200,no-lmr,eval=params.json")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("second")
+                .help("Second engine, same options as the first")
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("games")
+                .short("g")
+                .long("games")
+                .help("Number of games, colors alternate")
+                .default_value("100")
+                .takes_value(true))
+            .arg(Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .help("Games played at the same time, one per core if missing")
+                .takes_value(true))
+            .arg(Arg::with_name("random-plies")
+                .long("random-plies")
+                .help("Random plies at the start of each pair of games")
+                .takes_value(true))
+            .arg(Arg::with_name("max-plies")
+                .long("max-plies")
+                .help("Plies after which a game is a draw")
+                .takes_value(true))
+            .arg(Arg::with_name("elo0")
+                .long("elo0")
+                .help("Elo difference of the SPRT null hypothesis")
+                .default_value("0")
+                .takes_value(true))
+            .arg(Arg::with_name("elo1")
+                .long("elo1")
+                .help("Elo difference of the SPRT alternative hypothesis")
+                .default_value("20")
+                .takes_value(true))
+            .arg(Arg::with_name("no-sprt")
+                .long("no-sprt")
+                .help("Play every game instead of stopping when the SPRT is decided"))
+            .arg(Arg::with_name("records")
+                .long("records")
+                .help("Directory where the game records are saved")
+                .takes_value(true))
+            .arg(rules_arg()))
+        .subcommand(SubCommand::with_name("tune")
+            .about("Tunes the heuristic weights on the positions of finished games")
+            .arg(Arg::with_name("records")
+                .help("Game record files or directories of them, such as arena --records output")
+                .required(true)
+                .multiple(true)
+                .index(1))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("File where the tuned weights are written after every pass")
+                .default_value("tuned.json")
+                .takes_value(true))
+            .arg(Arg::with_name("eval")
+                .long("eval")
+                .help("JSON file of the starting weights, the defaults if missing")
+                .takes_value(true))
+            .arg(evaluator_arg("evaluator", "Evaluation whose weights are tuned"))
+            .arg(Arg::with_name("skip-plies")
+                .long("skip-plies")
+                .help("Plies skipped at the start of every game")
+                .takes_value(true))
+            .arg(Arg::with_name("passes")
+                .long("passes")
+                .help("Most passes over the weights")
+                .takes_value(true))
+            .arg(Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .help("Threads computing the error, one per core if missing")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("explain")
+            .about("Prints every term of the evaluation of a position")
+            .arg(Arg::with_name("position")
+                .long("position")
+                .help("Position in text notation, the initial position if missing")
+                .conflicts_with("record")
+                .takes_value(true))
+            .arg(Arg::with_name("record")
+                .long("record")
+                .help("Game record to take the position from")
+                .takes_value(true))
+            .arg(Arg::with_name("ply")
+                .long("ply")
+                .help("Plies of the game record played before the position, all if missing")
+                .requires("record")
+                .takes_value(true))
+            .arg(evaluator_arg("evaluator", "Evaluation to explain"))
+            .arg(Arg::with_name("eval")
+                .long("eval")
+                .help("JSON file of heuristic weights, missing weights keep their default")
+                .takes_value(true))
+            .arg(rules_arg()))
+        .subcommand(SubCommand::with_name("play")
+            .about("Plays a game against the engine in the terminal")
+            .arg(Arg::with_name("color")
+                .long("color")
+                .help("Your color")
+                .possible_values(&["white", "black"])
+                .default_value("white")
+                .takes_value(true))
+            .arg(Arg::with_name("position")
+                .long("position")
+                .help("Starting position in text notation, the initial position if missing")
+                .takes_value(true))
+            .arg(Arg::with_name("time")
+                .long("time")
+                .help("Seconds the engine thinks for each move")
+                .default_value("5")
+                .takes_value(true))
+            .args(&engine_args()))
+        .subcommand(SubCommand::with_name("perft")
+            .about("Counts the positions reached after every sequence of legal moves")
+            .arg(Arg::with_name("position")
+                .long("position")
+                .help("Position in text notation, the initial position if missing")
+                .takes_value(true))
+            .arg(Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .help("Number of moves")
+                .default_value("3")
+                .takes_value(true))
+            .arg(rules_arg()))
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        return analyze(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("replay") {
+        return replay(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        return serve(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("arena") {
+        return arena(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("tune") {
+        return tune_weights(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("explain") {
+        return explain(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("play") {
+        return play_game(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("perft") {
+        return perft(matches);
+    }
+
     let color: String = value_t!(matches, "color", String).unwrap().to_lowercase();
     if color.as_str() != WHITE && color.as_str() != BLACK {
         println!("Error: color can be white or black");
@@ -89,6 +326,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    let rules: &'static Ruleset = ruleset(&matches);
+
+    let config = engine_config(&matches)?;
+
     let color: String = value_t!(matches, "color", String).unwrap().to_lowercase();
 
     config_logs(format!("{}_{}.txt", Local::now().format("%Y-%m-%d_%H:%M:%S"), color));
@@ -105,10 +346,348 @@ fn main() -> Result<(), Box<dyn Error>> {
     address: {address}
     port: {port},
     timeout: {timeout}
+    hash: {hash} MB
+    threads: {threads}
+    rules: {rules}
+    engine: {engine:?}
+    search: {search:?}
+    evaluation: {white_eval} as white, {black_eval} as black
 
-    ", name=name, color=color, address=address, port=port, timeout=timeout);
+    ", name=name, color=color, address=address, port=port, timeout=timeout, hash=config.tt_size_mb, threads=config.threads, rules=rules.name, engine=config.kind, search=config.search,
+       white_eval=config.white_evaluator.name(), black_eval=config.black_evaluator.name());
 
-    let mut player = Player::init(name, color, address, port, timeout)?;
+    let mut player = Player::init(name, color, address, port, timeout, rules, config)?;
     player.game_loop();
     Ok(())
 }
+
+// Options shared by every command that runs a search
+fn engine_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("hash")
+            .long("hash")
+            .help("Transposition table size in MB")
+            .takes_value(true),
+        Arg::with_name("threads")
+            .long("threads")
+            .help("Number of search threads")
+            .takes_value(true),
+        rules_arg(),
+        Arg::with_name("engine")
+            .long("engine")
+            .help("Search algorithm")
+            .possible_values(&["alphabeta", "mcts"])
+            .default_value("alphabeta")
+            .takes_value(true),
+        Arg::with_name("playout")
+            .long("playout")
+            .help("How MCTS playouts choose their moves")
+            .possible_values(&["random", "heuristic"])
+            .default_value("heuristic")
+            .takes_value(true),
+        Arg::with_name("no-null-move")
+            .long("no-null-move")
+            .help("Disable null-move pruning"),
+        Arg::with_name("no-lmr")
+            .long("no-lmr")
+            .help("Disable late move reductions"),
+        Arg::with_name("eval")
+            .long("eval")
+            .help("JSON file of heuristic weights, missing weights keep their default")
+            .takes_value(true),
+        evaluator_arg("white-eval", "Evaluation when playing white"),
+        evaluator_arg("black-eval", "Evaluation when playing black")
+    ]
+}
+
+// Rule variant, one of the presets
+fn rules_arg() -> Arg<'static, 'static> {
+    let names: Vec<&str> = RULESETS.iter().map(|rules| rules.name).collect();
+    Arg::with_name("rules")
+        .long("rules")
+        .help("Rule variant")
+        .possible_values(&names)
+        .default_value(ASHTON.name)
+        .takes_value(true)
+}
+
+// Evaluation chosen among the available ones
+fn evaluator_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    let names: Vec<&str> = EVALUATORS.iter().map(|evaluator| evaluator.name()).collect();
+    Arg::with_name(name)
+        .long(name)
+        .help(help)
+        .possible_values(&names)
+        .default_value(EscapeEvaluator.name())
+        .takes_value(true)
+}
+
+// Threads of the commands that run in parallel, one per core by default
+fn jobs(matches: &ArgMatches) -> usize {
+    value_t!(matches, "jobs", usize).unwrap_or_else(|_| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+fn ruleset(matches: &ArgMatches) -> &'static Ruleset {
+    Ruleset::by_name(matches.value_of("rules").unwrap()).unwrap()
+}
+
+fn engine_config(matches: &ArgMatches) -> Result<EngineConfig, String> {
+    let mut config = EngineConfig {
+        kind: value_t!(matches, "engine", EngineKind).unwrap(),
+        tt_size_mb: value_t!(matches, "hash", usize).unwrap_or(DEFAULT_TT_SIZE_MB),
+        threads: value_t!(matches, "threads", usize).unwrap_or(DEFAULT_THREADS).max(1),
+        ..EngineConfig::default()
+    };
+    config.mcts.playout = value_t!(matches, "playout", Playout).unwrap();
+    config.search.null_move = !matches.is_present("no-null-move");
+    config.search.late_move_reductions = !matches.is_present("no-lmr");
+    if let Some(path) = matches.value_of("eval") {
+        config.eval = EvalParams::load(Path::new(path))?;
+    }
+    config.white_evaluator = evaluator_by_name(matches.value_of("white-eval").unwrap()).unwrap();
+    config.black_evaluator = evaluator_by_name(matches.value_of("black-eval").unwrap()).unwrap();
+    Ok(config)
+}
+
+// Searches the position given on the command line, or reviews a game, and prints the result
+fn analyze(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let rules = ruleset(matches);
+    let state = match matches.value_of("position") {
+        Some(notation) => State::from_notation(notation, rules)?,
+        None => State::with_rules(rules.first_turn.to_string(), rules)
+    };
+    let mut config = engine_config(matches)?;
+    config.depth = value_t!(matches, "depth", u32).unwrap_or(config.depth);
+    let time: f64 = value_t!(matches, "time", f64)?;
+    let time = Duration::from_secs_f64(time);
+    if let Some(path) = matches.value_of("record") {
+        let record = GameRecord::load(Path::new(path))?;
+        let threshold = value_t!(matches, "threshold", i64).unwrap_or(BLUNDER_THRESHOLD);
+        // Scores of the same depth are comparable from one position to the next
+        config.search.best_iteration = false;
+        return review(&record, &mut Engine::new(config), time, threshold);
+    }
+    let mut engine = Engine::new(config);
+
+    println!("{}", state.board);
+    println!("{}", state.to_notation());
+    match engine.search(&state, Instant::now() + time) {
+        Some(info) => println!("{} to move, best move {}\n{}", state.turn, info.best_move().unwrap(), info),
+        None => println!("{} has no legal move", state.turn)
+    }
+    Ok(())
+}
+
+// Prints the search of every position of a game and the moves that lost at least threshold
+fn review(record: &GameRecord, engine: &mut Engine, time: Duration, threshold: i64) -> Result<(), Box<dyn Error>> {
+    println!("{} (white) - {} (black), {}, {} rules", record.white, record.black, record.date, record.rules.name);
+    let mut nodes: u64 = 0;
+    let blunders = analysis::review_game(record, engine, time, threshold, |analysis, found| {
+        for blunder in found {
+            let best = blunder.best.map_or("nothing".to_string(), |m| m.to_string());
+            let lost = blunder.score == if blunder.color == WHITE { i32::MIN } else { i32::MAX };
+            let loss = if lost { "the game".to_string() } else { blunder.loss.to_string() };
+            println!("    ?? {}. {} by {} loses {}, {} was best", blunder.ply + 1, blunder.played, blunder.color, loss, best);
+        }
+        if analysis.ply > 0 {
+            println!("{}. {}", analysis.ply, record.moves[analysis.ply - 1]);
+        }
+        match &analysis.info {
+            Some(info) => {
+                nodes += info.nodes;
+                println!("    {} to move, best move {}, {}", analysis.state.turn, info.best_move().unwrap(), info);
+            },
+            None => println!("    {}", outcome(&analysis.state).1)
+        }
+    })?;
+
+    println!("\n{} {}, {} nodes searched", record.result, record.termination, nodes);
+    for color in [WHITE, BLACK].iter() {
+        let moves: Vec<String> = blunders.iter()
+            .filter(|blunder| blunder.color == *color)
+            .map(|blunder| format!("{}. {}", blunder.ply + 1, blunder.played))
+            .collect();
+        println!("{} blunders: {}", color, if moves.is_empty() { "none".to_string() } else { moves.join(", ") });
+    }
+    Ok(())
+}
+
+// Prints the positions of a saved game one after the other
+fn replay(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let record = GameRecord::load(Path::new(matches.value_of("record").unwrap()))?;
+    println!("{} (white) - {} (black), {}, {} rules", record.white, record.black, record.date, record.rules.name);
+    let states = record.states()?;
+    println!("{}", states[0].board);
+    for (m, state) in record.moves.iter().zip(states.iter().skip(1)) {
+        println!("\n{}\n{}", m, state.board);
+    }
+    println!("\n{} {}", record.result, record.termination);
+    Ok(())
+}
+
+// Plays one game between two connected players and saves its record
+fn serve(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    config_logs(format!("{}_server.txt", Local::now().format("%Y-%m-%d_%H:%M:%S")));
+    let address = matches.value_of("address").unwrap();
+    let white_port: u32 = value_t!(matches, "white-port", u32).unwrap_or(DEFAULT_WHITE_PORT);
+    let black_port: u32 = value_t!(matches, "black-port", u32).unwrap_or(DEFAULT_BLACK_PORT);
+    let timeout: u64 = value_t!(matches, "timeout", u64)?;
+
+    let server = Server::bind(address, white_port, black_port)?;
+    let record = server.play(ruleset(matches), Duration::from_secs(timeout))?;
+    let path = PathBuf::from(format!("games/{}_server.txt", Local::now().format("%Y-%m-%d_%H:%M:%S")));
+    record.save(&path)?;
+    info!("Game record saved to {}", path.display());
+    Ok(())
+}
+
+// Plays a match between two engine configurations and prints the score, Elo and SPRT verdict
+fn arena(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let first: Contender = matches.value_of("first").unwrap().parse()?;
+    let second: Contender = matches.value_of("second").unwrap().parse()?;
+    let sprt = Sprt {
+        elo0: value_t!(matches, "elo0", f64)?,
+        elo1: value_t!(matches, "elo1", f64)?,
+        alpha: 0.05,
+        beta: 0.05
+    };
+    let config = ArenaConfig {
+        rules: ruleset(matches),
+        games: value_t!(matches, "games", usize)?,
+        jobs: jobs(matches),
+        random_plies: value_t!(matches, "random-plies", usize).unwrap_or(ARENA_RANDOM_PLIES),
+        max_plies: value_t!(matches, "max-plies", usize).unwrap_or(ARENA_MAX_PLIES),
+        sprt: if matches.is_present("no-sprt") { None } else { Some(sprt) }
+    };
+    let records = matches.value_of("records").map(PathBuf::from);
+
+    println!("{} vs {}, {} games on {} threads, {} rules", first.name, second.name, config.games, config.jobs, config.rules.name);
+    let score = run_arena(&first, &second, &config, |game, record, score| {
+        println!("Game {}: {} - {} {} {}, score {}", game + 1, record.white, record.black, record.result, record.termination, score);
+        if let Some(directory) = &records {
+            if let Err(e) = record.save(&directory.join(format!("game_{}.txt", game + 1))) {
+                println!("Could not save the game record: {}", e);
+            }
+        }
+    });
+
+    let (elo, margin) = score.elo();
+    let (lower, upper) = sprt.bounds();
+    println!("Score of {} vs {}: {} in {} games", first.name, second.name, score, score.games());
+    println!("Elo difference: {:.1} +/- {:.1}", elo, margin);
+    println!("SPRT elo0={} elo1={}: LLR {:.2} ({:.2}, {:.2}), {}", sprt.elo0, sprt.elo1,
+             score.llr(sprt.elo0, sprt.elo1), lower, upper, sprt.verdict(&score));
+    Ok(())
+}
+
+// Fits the heuristic weights to the results of recorded games and saves them
+fn tune_weights(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let paths: Vec<PathBuf> = matches.values_of("records").unwrap().map(PathBuf::from).collect();
+    let output = PathBuf::from(matches.value_of("output").unwrap());
+    let start = match matches.value_of("eval") {
+        Some(path) => EvalParams::load(Path::new(path))?,
+        None => EvalParams::default()
+    };
+    let evaluator = evaluator_by_name(matches.value_of("evaluator").unwrap()).unwrap();
+    let skip = value_t!(matches, "skip-plies", usize).unwrap_or(TUNER_SKIP_PLIES);
+    let passes = value_t!(matches, "passes", usize).unwrap_or(TUNER_MAX_PASSES);
+    let jobs = jobs(matches);
+
+    let samples = load_samples(&paths, skip)?;
+    if samples.is_empty() {
+        return Err("no positions of finished games".into());
+    }
+    let k = fit_scale(&samples, evaluator, &start, jobs);
+    println!("{} positions, scale {:.4}, error {:.6}", samples.len(), k, tuner::error(&samples, evaluator, &start, k, jobs));
+    let mut saved: Result<(), String> = Ok(());
+    tune(&samples, evaluator, &start, k, passes, jobs, |pass, params, error| {
+        println!("Pass {}: error {:.6}", pass, error);
+        saved = params.save(&output);
+    });
+    saved?;
+    println!("Tuned weights saved to {}", output.display());
+    Ok(())
+}
+
+// Prints the evaluation terms of a position next to its board
+fn explain(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let state = match matches.value_of("record") {
+        Some(path) => {
+            let record = GameRecord::load(Path::new(path))?;
+            let mut states = record.states()?;
+            let ply = value_t!(matches, "ply", usize).unwrap_or(record.moves.len());
+            if ply >= states.len() {
+                return Err(format!("the game has {} plies", record.moves.len()).into());
+            }
+            states.swap_remove(ply)
+        },
+        None => {
+            let rules = ruleset(matches);
+            match matches.value_of("position") {
+                Some(notation) => State::from_notation(notation, rules)?,
+                None => State::with_rules(rules.first_turn.to_string(), rules)
+            }
+        }
+    };
+    let params = match matches.value_of("eval") {
+        Some(path) => EvalParams::load(Path::new(path))?,
+        None => EvalParams::default()
+    };
+    let evaluator = evaluator_by_name(matches.value_of("evaluator").unwrap()).unwrap();
+    let explanation = evaluator.explain(&state, &params);
+
+    let board = state.board.to_string();
+    let width = board.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let text = format!("{} to move, {}\n\n{}", state.turn, state.to_notation(), explanation);
+    let mut right = text.lines();
+    for line in board.lines() {
+        println!("{}", format!("{:<width$}   {}", line, right.next().unwrap_or(""), width = width).trim_end());
+    }
+    for line in right {
+        println!("{:<width$}   {}", "", line, width = width);
+    }
+    Ok(())
+}
+
+// Plays against a human on the terminal and saves the game
+fn play_game(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let rules = ruleset(matches);
+    let state = match matches.value_of("position") {
+        Some(notation) => State::from_notation(notation, rules)?,
+        None => State::with_rules(rules.first_turn.to_string(), rules)
+    };
+    let human = matches.value_of("color").unwrap();
+    let time: u64 = value_t!(matches, "time", u64)?;
+    let mut engine = Engine::new(engine_config(matches)?);
+
+    println!("You play {}, type help for the commands", human);
+    let stdin = std::io::stdin();
+    let record = play::play(stdin.lock(), &mut std::io::stdout(), state, human, &mut engine, Duration::from_secs(time))?;
+    if !record.moves.is_empty() {
+        let path = PathBuf::from(format!("games/{}_play.txt", Local::now().format("%Y-%m-%d_%H:%M:%S")));
+        record.save(&path)?;
+        println!("Game record saved to {}", path.display());
+    }
+    Ok(())
+}
+
+// Prints the perft count after each legal move of a position and their sum
+fn perft(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let rules = ruleset(matches);
+    let mut state = match matches.value_of("position") {
+        Some(notation) => State::from_notation(notation, rules)?,
+        None => State::with_rules(rules.first_turn.to_string(), rules)
+    };
+    let depth: u32 = value_t!(matches, "depth", u32)?;
+
+    let start = Instant::now();
+    let divide = rules::perft_divide(&mut state, depth);
+    let elapsed = start.elapsed();
+    for (m, nodes) in divide.iter() {
+        println!("{}: {}", m, nodes);
+    }
+    let nodes: u64 = if depth == 0 { 1 } else { divide.iter().map(|(_, nodes)| nodes).sum() };
+    println!("\n{} moves, {} nodes at depth {} in {:?} ({:.0} nodes/s)",
+             divide.len(), nodes, depth, elapsed, nodes as f64 / elapsed.as_secs_f64());
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use crate::constants::*;
+use crate::game::{Move, State, Status};
+use crate::rules::{legal_moves, game_status};
+use crate::search::{random_move, SearchInfo};
+use crate::eval::{EvalParams, Evaluator};
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::str::FromStr;
+use std::time::Instant;
+use log::info;
+
+// How the moves of a playout are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playout {
+    // Uniformly random moves, as search_random
+    Random,
+    // Best move by heuristic among a few random candidates
+    Heuristic
+}
+
+impl FromStr for Playout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Playout, String> {
+        match s {
+            "random" => Ok(Playout::Random),
+            "heuristic" => Ok(Playout::Heuristic),
+            _ => Err(format!("unknown playout {}, expected random or heuristic", s))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MctsConfig {
+    pub playout: Playout,
+    // UCT exploration constant
+    pub exploration: f64,
+    // Stops before the deadline after this many iterations
+    pub max_iterations: u64,
+    // Fixed seed for reproducible searches, taken from the OS otherwise
+    pub seed: Option<u64>
+}
+
+impl Default for MctsConfig {
+    fn default() -> MctsConfig {
+        MctsConfig {
+            playout: Playout::Heuristic,
+            exploration: MCTS_EXPLORATION,
+            max_iterations: u64::MAX,
+            seed: None
+        }
+    }
+}
+
+struct Node {
+    // Move that leads to the node, None for the root
+    m: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Legal moves not expanded yet
+    untried: Vec<Move>,
+    white_to_move: bool,
+    // Result for white when the game is over in the node
+    result: Option<f64>,
+    visits: u64,
+    // Sum of the playout results, 1 is a white win and 0 a black win
+    white_score: f64
+}
+
+impl Node {
+    fn new(state: &State, m: Option<Move>, parent: Option<usize>) -> Node {
+        let result = white_result(state);
+        let untried = if result.is_none() { legal_moves(state) } else { vec![] };
+        Node {
+            m,
+            parent,
+            children: vec![],
+            untried,
+            white_to_move: state.color == WHITE,
+            result,
+            visits: 0,
+            white_score: 0.0
+        }
+    }
+
+    // Average result for the side that played the move leading to the node
+    fn mover_score(&self) -> f64 {
+        let white = self.white_score / self.visits as f64;
+        if self.white_to_move { 1.0 - white } else { white }
+    }
+
+    // The move leading to the node wins the game on the spot
+    fn is_win(&self) -> bool {
+        match self.result {
+            Some(result) => result == if self.white_to_move { 0.0 } else { 1.0 },
+            None => false
+        }
+    }
+}
+
+// Result of a finished game for white, None while it goes on
+fn white_result(state: &State) -> Option<f64> {
+    let white_to_move = state.color == WHITE;
+    match game_status(state) {
+        Status::WIN => Some(if white_to_move { 1.0 } else { 0.0 }),
+        Status::LOSS => Some(if white_to_move { 0.0 } else { 1.0 }),
+        Status::DRAW => Some(0.5),
+        Status::ONGOING => None
+    }
+}
+
+// Heuristic of an unfinished playout mapped to a white win probability
+fn white_estimate(state: &State, evaluator: &dyn Evaluator, params: &EvalParams) -> f64 {
+    let value = evaluator.evaluate(state, params) as f64;
+    1.0 / (1.0 + (-value / MCTS_EVAL_SCALE).exp())
+}
+
+fn playout_move(state: &State, playout: Playout, evaluator: &dyn Evaluator, params: &EvalParams, rng: &mut StdRng) -> Move {
+    match playout {
+        Playout::Random => random_move(state, rng),
+        Playout::Heuristic => {
+            let white_to_move = state.color == WHITE;
+            let mut best: Option<(i32, Move)> = None;
+            for _ in 0..MCTS_PLAYOUT_CANDIDATES {
+                let m = random_move(state, rng);
+                let mut next = state.clone();
+                next.make_move(&m);
+                let value = evaluator.evaluate(&next, params);
+                let better = match best {
+                    None => true,
+                    Some((best_value, _)) => if white_to_move { value > best_value } else { value < best_value }
+                };
+                if better {
+                    best = Some((value, m));
+                }
+            }
+            best.unwrap().1
+        }
+    }
+}
+
+// Plays from the state until the game ends or the playout is cut off
+fn simulate(mut state: State, playout: Playout, evaluator: &dyn Evaluator, params: &EvalParams, rng: &mut StdRng) -> f64 {
+    for _ in 0..MCTS_PLAYOUT_DEPTH {
+        if let Some(result) = white_result(&state) {
+            return result;
+        }
+        let m = playout_move(&state, playout, evaluator, params, rng);
+        state.make_move(&m);
+    }
+    white_result(&state).unwrap_or_else(|| white_estimate(&state, evaluator, params))
+}
+
+// Child with the highest UCT value
+fn select_child(tree: &[Node], node: usize, exploration: f64) -> usize {
+    let log_visits = (tree[node].visits as f64).ln();
+    let uct = |child: usize| {
+        let child = &tree[child];
+        child.mover_score() + exploration * (log_visits / child.visits as f64).sqrt()
+    };
+    let mut best = tree[node].children[0];
+    for &child in tree[node].children.iter().skip(1) {
+        if uct(child) > uct(best) {
+            best = child;
+        }
+    }
+    best
+}
+
+// Winning move if one was expanded, the most visited child otherwise
+fn most_visited_child(tree: &[Node], node: usize) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for &child in tree[node].children.iter() {
+        if tree[child].is_win() {
+            return Some(child);
+        }
+        if best.is_none_or(|b| tree[child].visits > tree[b].visits) {
+            best = Some(child);
+        }
+    }
+    best
+}
+
+// Monte Carlo Tree Search with UCT selection. The score of the returned info is the
+// white win rate of the root scaled to -1000..1000, nodes counts the iterations.
+pub fn mcts_search(state: &State, end_instant: Instant, config: &MctsConfig, evaluator: &dyn Evaluator, params: &EvalParams) -> Option<SearchInfo> {
+    let start_instant = Instant::now();
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy()
+    };
+    let mut tree: Vec<Node> = vec![Node::new(state, None, None)];
+    if tree[0].untried.is_empty() {
+        return None;
+    }
+
+    let mut root_state: State = state.clone();
+    let mut undos = vec![];
+    let mut iterations: u64 = 0;
+    while iterations < config.max_iterations && Instant::now() < end_instant {
+        // Selection
+        let mut node = 0;
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            node = select_child(&tree, node, config.exploration);
+            undos.push(root_state.make_move(&tree[node].m.unwrap()));
+        }
+
+        // Expansion
+        if !tree[node].untried.is_empty() {
+            let i = rng.gen_range(0, tree[node].untried.len());
+            let m = tree[node].untried.swap_remove(i);
+            undos.push(root_state.make_move(&m));
+            let child = Node::new(&root_state, Some(m), Some(node));
+            tree.push(child);
+            let child = tree.len() - 1;
+            tree[node].children.push(child);
+            node = child;
+        }
+
+        // Simulation
+        let result = simulate(root_state.clone(), config.playout, evaluator, params, &mut rng);
+
+        // Backpropagation
+        let mut current = Some(node);
+        while let Some(n) = current {
+            tree[n].visits += 1;
+            tree[n].white_score += result;
+            current = tree[n].parent;
+        }
+        while let Some(undo) = undos.pop() {
+            root_state.unmake_move(undo);
+        }
+        iterations += 1;
+    }
+
+    let mut pv: Vec<Move> = vec![];
+    let mut node = 0;
+    while let Some(child) = most_visited_child(&tree, node) {
+        pv.push(tree[child].m.unwrap());
+        node = child;
+    }
+    let white_rate = if tree[0].visits > 0 { tree[0].white_score / tree[0].visits as f64 } else { 0.5 };
+    let info = SearchInfo {
+        depth: pv.len() as u32,
+        pv,
+        score: ((white_rate * 2.0 - 1.0) * 1000.0).round() as i32,
+        nodes: iterations,
+        elapsed: start_instant.elapsed()
+    };
+    info!("MCTS {}", info);
+    Some(info)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::{Board, Position};
+    use crate::eval::EscapeEvaluator;
+    use std::time::Duration;
+
+    #[test]
+    fn test_mcts_search() {
+        let state = State::init(WHITE.to_string());
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        for playout in [Playout::Random, Playout::Heuristic].iter() {
+            let config = MctsConfig { playout: *playout, max_iterations: 200, seed: Some(7), ..MctsConfig::default() };
+            let info = mcts_search(&state, end_instant, &config, &EscapeEvaluator, &EvalParams::default()).unwrap();
+            assert_eq!(info.nodes, 200);
+            assert!(legal_moves(&state).contains(&info.best_move().unwrap()));
+            assert!(info.score >= -1000 && info.score <= 1000);
+
+            // The same seed gives the same search
+            let again = mcts_search(&state, end_instant, &config, &EscapeEvaluator, &EvalParams::default()).unwrap();
+            assert_eq!(info.pv, again.pv);
+        }
+    }
+
+    #[test]
+    fn test_mcts_finds_escape() {
+        // The king escapes by moving up to (2,0) or down to (2,8)
+        let mut state = State::init(WHITE.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 3, 0, 0, 0, 0, 0, 2],
+            [2, 2, 0, 0, 0, 0, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+        ]);
+        state.history.push(state.board);
+        state.history_keys.push(state.board.key());
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        let config = MctsConfig { max_iterations: 500, seed: Some(1), ..MctsConfig::default() };
+        let m = mcts_search(&state, end_instant, &config, &EscapeEvaluator, &EvalParams::default()).unwrap().best_move().unwrap();
+        assert_eq!(m.from, Position { x: 2, y: 3 });
+        assert!(m.to == Position { x: 2, y: 0 } || m.to == Position { x: 2, y: 8 });
+    }
+}
@@ -1,7 +1,9 @@
 use std::io::prelude::*;
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream};
 use std::io::Error;
+use std::time::Duration;
 use log::info;
+use crate::constants::MAX_MESSAGE_LENGTH;
 
 pub struct ServerConnection {
     stream: TcpStream
@@ -18,6 +20,20 @@ impl ServerConnection {
         })
     }
 
+    // Waits for a player on the server side
+    pub fn accept(listener: &TcpListener) -> Result<ServerConnection, Error> {
+        let (stream, address) = listener.accept()?;
+        info!("Accepted connection from {}", address);
+        Ok(ServerConnection {
+            stream
+        })
+    }
+
+    // Time a read may wait before failing, None to wait forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream.set_read_timeout(timeout)
+    }
+
     pub fn write_int(&mut self, n: u32) {
         self.stream.write(&n.to_be_bytes()).unwrap();
     }
@@ -27,6 +43,12 @@ impl ServerConnection {
         self.stream.write(s.as_bytes()).unwrap();
     }
 
+    // Writes a string, failing instead of panicking when the peer is gone
+    pub fn try_write_string(&mut self, s: &str) -> Result<(), Error> {
+        self.stream.write_all(&(s.len() as u32).to_be_bytes())?;
+        self.stream.write_all(s.as_bytes())
+    }
+
     pub fn read_int(&mut self) -> u32 {
         let mut buf = [0u8; 4];
         self.stream.read_exact(&mut buf).unwrap();
@@ -39,4 +61,17 @@ impl ServerConnection {
         self.stream.read(data.as_mut_slice()).unwrap();
         String::from_utf8(data).unwrap()
     }
+
+    // Reads a string, failing instead of panicking when the peer is gone or too slow
+    pub fn try_read_string(&mut self) -> Result<String, Error> {
+        let mut buf = [0u8; 4];
+        self.stream.read_exact(&mut buf)?;
+        let len = u32::from_be_bytes(buf) as usize;
+        if len > MAX_MESSAGE_LENGTH {
+            return Err(Error::new(std::io::ErrorKind::InvalidData, format!("message of {} bytes is too long", len)));
+        }
+        let mut data = vec![0u8; len];
+        self.stream.read_exact(data.as_mut_slice())?;
+        String::from_utf8(data).map_err(|e| Error::new(std::io::ErrorKind::InvalidData, e))
+    }
 }
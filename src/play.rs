@@ -0,0 +1,188 @@
+use crate::constants::*;
+use crate::engine::Engine;
+use crate::game::{Move, Position, State};
+use crate::record::{GameRecord, Outcome, outcome};
+use crate::rules::{legal_move, legal_moves};
+use std::io::{BufRead, Result, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use chrono::Local;
+
+// What the human can type at the prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Move(Move),
+    // Takes back the last move of the human and the engine's reply
+    Undo,
+    // Legal moves from a cell, or all of them
+    Moves(Option<Position>),
+    Resign,
+    Help,
+    Quit
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Command, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["undo"] => Ok(Command::Undo),
+            ["moves"] => Ok(Command::Moves(None)),
+            ["moves", cell] => Ok(Command::Moves(Some(cell.parse()?))),
+            ["resign"] => Ok(Command::Resign),
+            ["help"] => Ok(Command::Help),
+            ["quit"] => Ok(Command::Quit),
+            [m] => Ok(Command::Move(m.parse()?)),
+            _ => Err(format!("unknown command {}, type help for the list", s.trim()))
+        }
+    }
+}
+
+const HELP: &str = "Commands:
+  e3->h3     move the checker on e3 to h3
+  moves e3   legal moves of the checker on e3, moves alone lists all of them
+  undo       take back your last move and the engine's reply
+  resign     give up the game
+  quit       leave without a result";
+
+// Plays a game between the human, reading commands from input, and the engine, which thinks
+// move_time for each move. Returns the record of the game, unfinished when the human quits.
+pub fn play<R: BufRead, W: Write>(mut input: R, output: &mut W, start: State, human: &str, engine: &mut Engine, move_time: Duration) -> Result<GameRecord> {
+    let rules = start.board.rules();
+    let (white, black) = if human == WHITE { ("human", NAME) } else { (NAME, "human") };
+    let mut record = GameRecord::new(white, black, &Local::now().format("%Y.%m.%d").to_string(), rules);
+    if start.to_notation() != State::with_rules(rules.first_turn.to_string(), rules).to_notation() {
+        record.position = Some(start.to_notation());
+    }
+    let mut states: Vec<State> = vec![start];
+    let mut changed = true;
+    loop {
+        let state = states.last().unwrap().clone();
+        if changed {
+            writeln!(output, "{}", state.board)?;
+            changed = false;
+        }
+        let (result, reason) = outcome(&state);
+        if result != Outcome::Unfinished {
+            record.finish(&state);
+            writeln!(output, "{} {}", result, reason)?;
+            return Ok(record);
+        }
+
+        if state.turn != human {
+            let info = engine.search(&state, Instant::now() + move_time);
+            let m = info.as_ref().and_then(|info| info.best_move()).unwrap_or_else(|| legal_moves(&state)[0]);
+            match info {
+                Some(info) => writeln!(output, "{} plays {}, {}", NAME, m, info)?,
+                None => writeln!(output, "{} plays {}", NAME, m)?
+            }
+            let mut next = state.clone();
+            next.make_move(&m);
+            states.push(next);
+            record.moves.push(m);
+            changed = true;
+            continue;
+        }
+
+        write!(output, "{} to move> ", state.turn)?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(record);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Command>() {
+            Ok(Command::Move(m)) if legal_move(&state, &m) => {
+                let mut next = state.clone();
+                next.make_move(&m);
+                states.push(next);
+                record.moves.push(m);
+                changed = true;
+            },
+            Ok(Command::Move(m)) => writeln!(output, "{} is not a legal move", m)?,
+            Ok(Command::Undo) => {
+                // Back to the previous position where the human is to move
+                match states[..states.len() - 1].iter().rposition(|state| state.turn == human) {
+                    Some(ply) => {
+                        states.truncate(ply + 1);
+                        record.moves.truncate(ply);
+                        changed = true;
+                    },
+                    None => writeln!(output, "Nothing to undo")?
+                }
+            },
+            Ok(Command::Moves(cell)) => {
+                let moves: Vec<String> = legal_moves(&state).iter()
+                    .filter(|m| cell.is_none_or(|cell| m.from == cell))
+                    .map(|m| m.to_string())
+                    .collect();
+                if moves.is_empty() {
+                    writeln!(output, "No legal moves")?;
+                } else {
+                    writeln!(output, "{}", moves.join(" "))?;
+                }
+            },
+            Ok(Command::Resign) => {
+                record.result = if human == WHITE { Outcome::BlackWin } else { Outcome::WhiteWin };
+                record.termination = "resignation".to_string();
+                writeln!(output, "{} resigns, {}", human, record.result)?;
+                return Ok(record);
+            },
+            Ok(Command::Help) => writeln!(output, "{}", HELP)?,
+            Ok(Command::Quit) => return Ok(record),
+            Err(e) => writeln!(output, "{}", e)?
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::ruleset::ASHTON;
+
+    fn session(commands: &str, human: &str) -> (GameRecord, String) {
+        let mut engine = Engine::new(EngineConfig { depth: 1, tt_size_mb: 1, ..EngineConfig::default() });
+        let mut output: Vec<u8> = vec![];
+        let start = State::with_rules(WHITE.to_string(), &ASHTON);
+        let record = play(commands.as_bytes(), &mut output, start, human, &mut engine, Duration::from_secs(10)).unwrap();
+        (record, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!("e3->h3".parse::<Command>(), Ok(Command::Move("e3->h3".parse().unwrap())));
+        assert_eq!(" moves e3\n".parse::<Command>(), Ok(Command::Moves(Some(Position { x: 4, y: 2 }))));
+        assert_eq!("moves".parse::<Command>(), Ok(Command::Moves(None)));
+        assert_eq!("undo".parse::<Command>(), Ok(Command::Undo));
+        assert!("e3-h3".parse::<Command>().is_err());
+        assert!("moves e3 e4".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_play() {
+        let (record, output) = session("moves e3\nd1->d2\ne5->e4\ne3->h3\nundo\nundo\nhelp\nresign\n", WHITE);
+        assert!(output.contains("e3->f3 e3->g3 e3->h3 e3->i3 e3->d3 e3->c3 e3->b3 e3->a3\n"));
+        assert!(output.contains("d1->d2 is not a legal move"));
+        assert!(output.contains("e5->e4 is not a legal move"));
+        assert!(output.contains(&format!("{} plays", NAME)));
+        assert!(output.contains("Nothing to undo"));
+        assert!(output.contains(HELP));
+        assert!(record.moves.is_empty());
+        assert_eq!((record.result, record.termination.as_str()), (Outcome::BlackWin, "resignation"));
+
+        // The engine opens as white and its move can not be taken back, the game is left
+        // unfinished at the end of the input
+        let (record, output) = session("undo\nmoves a4\n", BLACK);
+        assert_eq!(record.moves.len(), 1);
+        assert_eq!(output.matches(&format!("{} plays", NAME)).count(), 1);
+        assert!(output.contains("Nothing to undo"));
+        assert!(output.contains("a4->a3 a4->a2"));
+        assert_eq!(record.result, Outcome::Unfinished);
+        assert_eq!((record.white.as_str(), record.black.as_str()), (NAME, "human"));
+    }
+}
@@ -1,44 +1,67 @@
 use crate::network::ServerConnection;
 use crate::game::{State, Status, Move};
 use crate::rules::game_status;
-use crate::search::iterative_time_bound_alpha_beta_search;
+use crate::engine::{Engine, EngineConfig};
+use crate::ruleset::Ruleset;
+use crate::record::{GameRecord, Outcome, infer_move};
 use crate::serialization::*;
+use crate::constants::*;
 use std::io::Error;
+use std::path::PathBuf;
 use log::{info};
 use std::time::{Instant, Duration};
+use chrono::Local;
 
 pub struct Player {
      connection: ServerConnection,
      state: State,
-     timeout: u64
+     timeout: u64,
+     engine: Engine,
+     record: GameRecord
  }
 
  impl Player {
-     pub fn init(name: String, color: String, address: String, port: u32, timeout: u64) -> Result<Player, Error> {
+     pub fn init(name: String, color: String, address: String, port: u32, timeout: u64, rules: &'static Ruleset, config: EngineConfig) -> Result<Player, Error> {
          let mut connection = ServerConnection::connect(&address, port)?;
          connection.write_string(&name);
+         let (white, black) = if color == WHITE { (name.as_str(), "?") } else { ("?", name.as_str()) };
+         let record = GameRecord::new(white, black, &Local::now().format("%Y.%m.%d").to_string(), rules);
          Ok(Player {
              connection,
-             state: State::init(color),
-             timeout
+             state: State::with_rules(color, rules),
+             timeout,
+             engine: Engine::new(config),
+             record
          })
      }
 
      fn make_move(&mut self) {
          let start_instant = Instant::now();
          let end_instant = start_instant.checked_add(Duration::new(self.timeout-1, 0)).unwrap();
-         let m: Move = iterative_time_bound_alpha_beta_search(&self.state, 6, end_instant).unwrap();
+         let info = self.engine.search(&self.state, end_instant).unwrap();
+         let m: Move = info.best_move().unwrap();
          // let m: Move = alpha_beta_search(&self.state, 3).0.unwrap();
-         info!("Chosen move: {} in {:?}", m, start_instant.elapsed());
+         info!("Chosen move: {} in {:?}, expected line: {}", m, start_instant.elapsed(), info);
          self.connection.write_string(&serialize_move(&m, &self.state.color));
      }
 
      fn receive_game_state(&mut self)  {
          let res: String = self.connection.read_string();
-         self.state.board = deserialize_board(&res);
+         let previous_board = self.state.board;
+         self.state.board = deserialize_board(&res, self.state.board.rules());
+         if let Some(m) = infer_move(&previous_board, &self.state.board) {
+             self.record.moves.push(m);
+         }
          self.state.turn = deserialize_turn(&res);
+         self.state.board.set_turn(&self.state.turn);
          self.state.history.push(self.state.board);
-         self.state.status = game_status(&self.state);
+         self.state.history_keys.push(self.state.board.key());
+         self.state.status = match self.state.turn.as_str() {
+             "whitewin" => if self.state.color == WHITE { Status::WIN } else { Status::LOSS },
+             "blackwin" => if self.state.color == BLACK { Status::WIN } else { Status::LOSS },
+             "draw" => Status::DRAW,
+             _ => game_status(&self.state)
+         };
      }
 
 
@@ -66,5 +89,31 @@ pub struct Player {
              }
          }
          info!("Game ended.");
+         self.save_record();
+     }
+
+     // Writes the game to games/, next to the logs
+     fn save_record(&mut self) {
+         // The server replaces the side to move with the result at the end of the game
+         let server_result = match self.state.turn.as_str() {
+             "whitewin" => Outcome::WhiteWin,
+             "blackwin" => Outcome::BlackWin,
+             "draw" => Outcome::Draw,
+             _ => Outcome::Unfinished
+         };
+         let mut state = self.state.clone();
+         if let Some(m) = self.record.moves.last() {
+             state.turn = if state.board.cell_content(m.to) == B { WHITE } else { BLACK }.to_string();
+         }
+         self.record.finish(&state);
+         if server_result != Outcome::Unfinished && server_result != self.record.result {
+             self.record.result = server_result;
+             self.record.termination = "server".to_string();
+         }
+         let path = PathBuf::from(format!("games/{}_{}.txt", Local::now().format("%Y-%m-%d_%H:%M:%S"), self.state.color));
+         match self.record.save(&path) {
+             Ok(()) => info!("Game record saved to {}", path.display()),
+             Err(e) => info!("Could not save the game record: {}", e)
+         }
      }
  }
\ No newline at end of file
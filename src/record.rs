@@ -0,0 +1,304 @@
+use crate::constants::*;
+use crate::game::{Board, Move, State, Status};
+use crate::rules::{legal_moves, game_status};
+use crate::ruleset::{Ruleset, ASHTON};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    Unfinished
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            Outcome::WhiteWin => "1-0",
+            Outcome::BlackWin => "0-1",
+            Outcome::Draw => "1/2-1/2",
+            Outcome::Unfinished => "*"
+        };
+        write!(f, "{}", result)
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Outcome, String> {
+        match s {
+            "1-0" => Ok(Outcome::WhiteWin),
+            "0-1" => Ok(Outcome::BlackWin),
+            "1/2-1/2" => Ok(Outcome::Draw),
+            "*" => Ok(Outcome::Unfinished),
+            _ => Err(format!("unknown result {}", s))
+        }
+    }
+}
+
+// A played game in a PGN-like text format: one [Tag "value"] header per line, an empty
+// line, then the numbered moves in Move's notation followed by the result
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    pub date: String,
+    pub rules: &'static Ruleset,
+    // Starting position in text notation, None for the initial position of the rules
+    pub position: Option<String>,
+    pub moves: Vec<Move>,
+    pub result: Outcome,
+    // Why the game ended, empty while it goes on
+    pub termination: String
+}
+
+impl GameRecord {
+    pub fn new(white: &str, black: &str, date: &str, rules: &'static Ruleset) -> GameRecord {
+        GameRecord {
+            white: white.to_string(),
+            black: black.to_string(),
+            date: date.to_string(),
+            rules,
+            position: None,
+            moves: vec![],
+            result: Outcome::Unfinished,
+            termination: String::new()
+        }
+    }
+
+    // Position before the first move, the side to move plays next
+    pub fn start(&self) -> Result<State, String> {
+        match &self.position {
+            Some(notation) => State::from_notation(notation, self.rules),
+            None => Ok(State::with_rules(self.rules.first_turn.to_string(), self.rules))
+        }
+    }
+
+    // Every position of the game, from the start to the position after the last move
+    pub fn states(&self) -> Result<Vec<State>, String> {
+        let mut state = self.start()?;
+        let mut states: Vec<State> = Vec::with_capacity(self.moves.len() + 1);
+        states.push(state.clone());
+        for (i, m) in self.moves.iter().enumerate() {
+            if game_status(&state) != Status::ONGOING {
+                return Err(format!("move {} {} played after the end of the game", i + 1, m));
+            }
+            if !legal_moves(&state).contains(m) {
+                return Err(format!("move {} {} is not legal", i + 1, m));
+            }
+            state.make_move(m);
+            states.push(state.clone());
+        }
+        Ok(states)
+    }
+
+    // Sets the result and termination from the final position
+    pub fn finish(&mut self, state: &State) {
+        let (result, termination) = outcome(state);
+        self.result = result;
+        self.termination = termination.to_string();
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, self.to_string()).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<GameRecord, String> {
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?.parse()
+    }
+}
+
+// Result of the position for white and black, with the reason the game ended
+pub fn outcome(state: &State) -> (Outcome, &'static str) {
+    let mut mover = state.clone();
+    mover.color = state.turn.clone();
+    let white_to_move = state.turn == WHITE;
+    let board = &state.board;
+    let status = game_status(&mover);
+    let win = |white_wins: bool| if white_wins { Outcome::WhiteWin } else { Outcome::BlackWin };
+    match status {
+        Status::ONGOING => (Outcome::Unfinished, ""),
+        Status::DRAW => (Outcome::Draw, "repetition"),
+        Status::WIN | Status::LOSS => {
+            let white_wins = (status == Status::WIN) == white_to_move;
+            let reason = match board.king_cell() {
+                None => "king captured",
+                Some(king) if board.rules().escapes.contains(king) => "king escaped",
+                Some(_) => "no legal moves"
+            };
+            (win(white_wins), reason)
+        }
+    }
+}
+
+// Move that turns one board into the next, None when they do not differ by a single move
+pub fn infer_move(before: &Board, after: &Board) -> Option<Move> {
+    let pieces = [(before.white(), after.white()), (before.king(), after.king()), (before.black(), after.black())];
+    for (old, new) in pieces.iter() {
+        let from = *old & !*new;
+        let to = *new & !*old;
+        if from.count() == 1 && to.count() == 1 {
+            return Some(Move { from: from.first().unwrap(), to: to.first().unwrap() });
+        }
+    }
+    None
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[White \"{}\"]", self.white)?;
+        writeln!(f, "[Black \"{}\"]", self.black)?;
+        writeln!(f, "[Date \"{}\"]", self.date)?;
+        writeln!(f, "[Rules \"{}\"]", self.rules.name)?;
+        if let Some(position) = &self.position {
+            writeln!(f, "[Position \"{}\"]", position)?;
+        }
+        writeln!(f, "[Result \"{}\"]", self.result)?;
+        writeln!(f, "[Termination \"{}\"]", self.termination)?;
+        writeln!(f)?;
+
+        // Black moves first when the starting position or the rules say so
+        let black_first = match &self.position {
+            Some(position) => position.split_whitespace().nth(1) == Some("b"),
+            None => self.rules.first_turn == BLACK
+        };
+        let mut number = 1;
+        let mut moves = self.moves.iter();
+        if black_first {
+            if let Some(m) = moves.next() {
+                writeln!(f, "1... {}", m)?;
+                number += 1;
+            }
+        }
+        let moves: Vec<&Move> = moves.collect();
+        for pair in moves.chunks(2) {
+            let line: Vec<String> = pair.iter().map(|m| m.to_string()).collect();
+            writeln!(f, "{}. {}", number, line.join(" "))?;
+            number += 1;
+        }
+        writeln!(f, "{}", self.result)
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GameRecord, String> {
+        let mut record = GameRecord::new("?", "?", "?", &ASHTON);
+        let mut movetext: Vec<&str> = vec![];
+        for line in s.lines().map(|line| line.trim()) {
+            if line.starts_with('[') && line.ends_with(']') {
+                let tag = &line[1..line.len() - 1];
+                let (name, value) = tag.split_at(tag.find(' ').ok_or(format!("bad header {}", line))?);
+                let value = value.trim().trim_matches('"');
+                match name {
+                    "White" => record.white = value.to_string(),
+                    "Black" => record.black = value.to_string(),
+                    "Date" => record.date = value.to_string(),
+                    "Rules" => record.rules = Ruleset::by_name(value).ok_or(format!("unknown rules {}", value))?,
+                    "Position" => record.position = Some(value.to_string()),
+                    "Result" => record.result = value.parse()?,
+                    "Termination" => record.termination = value.to_string(),
+                    _ => {}
+                }
+            } else {
+                movetext.extend(line.split_whitespace());
+            }
+        }
+        for token in movetext {
+            if token.ends_with('.') {
+                continue;
+            }
+            if let Ok(result) = token.parse::<Outcome>() {
+                record.result = result;
+                continue;
+            }
+            record.moves.push(token.parse()?);
+        }
+        Ok(record)
+    }
+}
+
+// A short game where the king escapes, shared by the tests of several modules
+#[cfg(test)]
+pub(crate) fn escape_game() -> GameRecord {
+    let mut record = GameRecord::new("muscovite", "opponent", "2020.05.01", &ASHTON);
+    for m in ["e3->h3", "d1->d2", "e4->b4", "e2->g2", "e5->e3", "d2->c2", "e3->a3"].iter() {
+        record.moves.push(m.parse().unwrap());
+    }
+    record.finish(record.states().unwrap().last().unwrap());
+    record
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Position;
+    use crate::ruleset::HNEFATAFL;
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut record = escape_game();
+        let states = record.states().unwrap();
+        assert_eq!(states.len(), record.moves.len() + 1);
+        let last = states.last().unwrap();
+        assert_eq!(last.board.king_cell(), Some(Position { x: 0, y: 2 }));
+        record.finish(last);
+        assert_eq!(record.result, Outcome::WhiteWin);
+        assert_eq!(record.termination, "king escaped");
+
+        let text = record.to_string();
+        assert!(text.starts_with("[White \"muscovite\"]\n"));
+        assert!(text.contains("\n1. e3->h3 d1->d2\n"));
+        assert!(text.ends_with("\n4. e3->a3\n1-0\n"));
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.moves, record.moves);
+        assert_eq!(parsed.states().unwrap().last().unwrap().board, last.board);
+
+        // From a given position with black to move
+        let mut record = GameRecord::new("a", "b", "?", &ASHTON);
+        record.position = Some(states[1].to_notation());
+        record.moves = escape_game().moves[1..].to_vec();
+        let text = record.to_string();
+        assert!(text.contains("\n1... d1->d2\n2. e4->b4 e2->g2\n"));
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed.states().unwrap().last().unwrap().board, last.board);
+
+        // Black opens in Hnefatafl
+        let mut record = GameRecord::new("a", "b", "?", &HNEFATAFL);
+        record.moves.push("d1->d3".parse().unwrap());
+        let text = record.to_string();
+        assert!(text.contains("\n1... d1->d3\n"));
+        assert_eq!(text.parse::<GameRecord>().unwrap().states().unwrap()[1].turn, WHITE);
+    }
+
+    #[test]
+    fn test_record_errors() {
+        let mut record = escape_game();
+        record.moves.push("a4->a3".parse().unwrap());
+        assert!(record.states().is_err());
+        record.moves.truncate(2);
+        record.moves.push("e5->e4".parse().unwrap());
+        assert!(record.states().is_err());
+        assert!("[Rules \"chess\"]\n".parse::<GameRecord>().is_err());
+        assert!("1. e3->h3 e1-e2".parse::<GameRecord>().is_err());
+    }
+
+    #[test]
+    fn test_infer_move() {
+        let states = escape_game().states().unwrap();
+        for (i, m) in escape_game().moves.iter().enumerate() {
+            assert_eq!(infer_move(&states[i].board, &states[i + 1].board), Some(*m));
+        }
+        assert_eq!(infer_move(&states[0].board, &states[0].board), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::game::{Move, Position, Status, State, Board};
+use crate::bitboard::{step, Bitboard, BitboardIter, Direction, DIRECTIONS};
 use crate::constants::*;
 // use log::debug;
 
@@ -31,28 +32,62 @@ fn get_one_cell_closer(from: Position, to: Position) -> Option<Position> {
 
 // Returns true if a cell is a barrier
 pub fn is_barrier(board: &Board, cell: Position) -> bool {
-    let cell_type = board.cell_type(cell);
-    board.is_empty(cell) && (cell_type == C || cell_type == T)
+    board.barriers().contains(cell)
 }
 
 // Return true if there are obstacles
 pub fn obstacles(state: &State, m: &Move) -> bool {
     let mut from: Position = m.from;
     let to: Position = m.to.clone();
+    let (king_targets, targets) = target_cells(state);
+    let targets: Bitboard = if state.board.king().contains(m.from) { king_targets } else { targets };
+    let mut camp: Bitboard = camp_cells(state, m.from);
     while (from.x == to.x && from.y != to.y) ||
         (from.y == to.y && from.x != to.x) {
         from = get_one_cell_closer(from, to).unwrap();
-        if !is_legal_target_cell(state, from) {
+        if !(targets | camp).contains(from) {
             return true;
         }
+        if !camp.contains(from) {
+            camp = Bitboard::EMPTY;
+        }
     }
     return false;
 }
 
-// Checks if a cell is empty and regular
+// Checks if a cell is empty and not blocked for the side to move
 pub fn is_legal_target_cell(state: &State, cell: Position) -> bool {
-    let cell_type: u32 = state.board.cell_type(cell);
-    state.board.is_empty(cell) && (cell_type == R || cell_type == F)
+    !(state.board.occupied() | blocked_cells(state)).contains(cell)
+}
+
+// Cells the side to move may neither stop on nor cross
+fn blocked_cells(state: &State) -> Bitboard {
+    state.board.rules().blocked(state.color == BLACK)
+}
+
+// Cells the king and the other checkers of the side to move may stop on or cross: the empty
+// cells that are not blocked, without the escapes when only the king may reach them
+fn target_cells(state: &State) -> (Bitboard, Bitboard) {
+    let rules = state.board.rules();
+    let targets: Bitboard = !(state.board.occupied() | blocked_cells(state));
+    if rules.king_only_escapes {
+        (targets, targets & !rules.escapes)
+    } else {
+        (targets, targets)
+    }
+}
+
+// Empty camp cells the checker on from may stop on or cross until it leaves its camp. A black
+// checker on a camp never left it, since it may not come back, and it may only move within the
+// group of camp cells it starts from.
+fn camp_cells(state: &State, from: Position) -> Bitboard {
+    let board = &state.board;
+    let rules = board.rules();
+    if rules.camps.contains(from) && board.black().contains(from) {
+        rules.camp(from) & !board.occupied()
+    } else {
+        Bitboard::EMPTY
+    }
 }
 
 // Check if is a legal move
@@ -61,251 +96,143 @@ pub fn legal_move(state: &State, m: &Move) -> bool {
         return false;
     }
 
-    // Check obstacles
-    if obstacles(state, &m) {
+    // Orthogonal move of a checker of the side to move
+    if m.from.x != m.to.x && m.from.y != m.to.y {
         return false;
     }
-
-    if is_legal_target_cell(state, m.to) {
-        return true;
-    }
-    else if state.color == BLACK &&
-        state.board.cell_type(m.from) == C &&
-        state.board.cell_type(m.to) == C &&
-        state.board.is_empty(m.to) &&
-        (m.from.x as i32 - m.to.x as i32).abs() <= 2 {
-        return true;
+    if !movable_cells(state).any(|cell| cell == m.from) {
+        return false;
     }
-    return true;
+
+    // Check obstacles, the destination included
+    !obstacles(state, m)
 }
 
 // Returns all legal moves
 pub fn legal_moves(state: &State) -> Vec<Move> {
-    let board = &state.board;
-    let color = &state.color;
-    let mut moves: Vec<Move> =  vec![];
-    let mut cells: Vec<Position> = vec![];
+    let mut moves: Vec<Move> = Vec::with_capacity(128);
+    legal_moves_into(state, &mut moves);
+    moves
+}
 
-    if color == WHITE {
-        cells = board.white_cells();
-    }
-    else if color == BLACK {
-        cells = board.black_cells();
+// Checkers of the side to move, white checkers come before the king
+fn movable_cells(state: &State) -> std::iter::Chain<BitboardIter, BitboardIter> {
+    let board = &state.board;
+    if state.color == WHITE {
+        board.white().iter().chain(board.king().iter())
+    } else if state.color == BLACK {
+        board.black().iter().chain(Bitboard::EMPTY.iter())
+    } else {
+        Bitboard::EMPTY.iter().chain(Bitboard::EMPTY.iter())
     }
+}
 
-    for cell in cells {
-        let from: Position = cell;
-        let mut to: Position = from;
-
-        // Increment x
-        loop {
-            // Cell is on last column so no column is adjacent on the right
-            if to.x == 8 {
-                break
-            }
-            to.x += 1;
-            if !is_legal_target_cell(state, to) {
-                break
-            }
-            else if legal_move(&state, &Move { from, to }) {
-                moves.push(Move { from, to });
-            }
-        }
-        to = from;
-        // Decrement x
-        loop {
-            // Cell is on first column so no column is adjacent on the left
-            if to.x == 0 {
-                break
-            }
-            to.x -= 1;
-            if !is_legal_target_cell(state, to) {
-                break
-            }
-            else if legal_move(&state, &Move { from, to }) {
-                moves.push(Move { from, to });
-            }
-        }
-        to = from;
-        // Increment y
-        loop {
-            // Cell is on last row so no row is under
-            if to.y == 8 {
-                break
-            }
-            to.y += 1;
-            if !is_legal_target_cell(state, to) {
-                break
-            }
-            else if legal_move(&state, &Move { from, to }) {
-                moves.push(Move { from, to });
-            }
-        }
-        to = from;
-        // Decrement y
-        loop {
-            // Cell is on first row so no row is above
-            if to.y == 0 {
-                break
-            }
-            to.y -= 1;
-            if !is_legal_target_cell(state, to) {
-                break
-            }
-            else if legal_move(&state, &Move { from, to }) {
+// Fills a buffer with all legal moves, reusing its allocation
+pub fn legal_moves_into(state: &State, moves: &mut Vec<Move>) {
+    moves.clear();
+
+    let (king_targets, targets) = target_cells(state);
+    for from in movable_cells(state) {
+        let targets = if state.board.king().contains(from) { king_targets } else { targets };
+        let camp = camp_cells(state, from);
+        // Increment x, decrement x, increment y, decrement y
+        for direction in [Direction::Right, Direction::Left, Direction::Down, Direction::Up].iter() {
+            let mut camp = camp;
+            let mut cell: Option<Position> = step(from, *direction);
+            while let Some(to) = cell.filter(|to| (targets | camp).contains(*to)) {
                 moves.push(Move { from, to });
+                if !camp.contains(to) {
+                    camp = Bitboard::EMPTY;
+                }
+                cell = step(to, *direction);
             }
         }
     }
-    return moves
 }
 
-// Returns which checkers has been captured by a move
-pub fn captures(board: &Board, m: &Move) -> Vec<Position> {
-    let mut captured_checkers: Vec<Position> = vec![];
-    // Moved checker
-    let moved_checker: Position = m.to;
-
-    // Checks that two given cell content are opposite color. es W -> B
-    let same_color = |a: Position, b: Position| -> bool {
-        let ca = board.cell_color(a);
-        let cb = board.cell_color(b);
-        if ca.is_none() || cb.is_none() {
-            return false;
-        }
-        else if ca.unwrap() == cb.unwrap() {
-            return true;
-        }
-        return false;
-    };
-
-    // Check if a cell contains the king
-    let is_king = |cell: Position| -> bool {
-        board.cell_content(cell) == K
-    };
-
-    // Surrounding cells
-    let surrounding_cells: [Option<Position>; 4] = board.surrounding_cells(moved_checker);
+// Checks if the side to move has at least one legal move
+pub fn has_legal_moves(state: &State) -> bool {
+    let (king_targets, targets) = target_cells(state);
+    let king = state.board.king();
+    movable_cells(state).any(|cell| {
+        let targets = if king.contains(cell) { king_targets } else { targets };
+        !(Bitboard::from_position(cell).neighbours() & (targets | camp_cells(state, cell))).is_empty()
+    })
+}
 
-    let surrounded = |cell: Position| {
-        let surrounding_cells: [Option<Position>; 4] = board.surrounding_cells(cell);
-        if surrounding_cells.iter().any(|o| o.is_none()) {
-            return false;
-        }
-        if surrounding_cells.iter().all(|p| is_barrier(board, p.unwrap()) ||
-            board.cell_content(p.unwrap()) == B) {
-            return true;
-        }
-        return false;
+// Returns the checker captured in one direction from the moved checker, if any
+fn capture_in_direction(board: &Board, moved_checker: Bitboard, direction: Direction) -> Bitboard {
+    let rules = board.rules();
+    let king: Bitboard = board.king();
+    let (allies, anvils): (Bitboard, Bitboard) = if !(moved_checker & board.white()).is_empty() {
+        (board.white() | king, if rules.king_armed { board.white() | king } else { board.white() })
+    } else if !(moved_checker & king).is_empty() && rules.king_armed {
+        (board.white() | king, board.white() | king)
+    } else if !(moved_checker & board.black()).is_empty() {
+        (board.black(), board.black())
+    } else {
+        return Bitboard::EMPTY;
     };
+    let enemies: Bitboard = board.occupied() & !allies;
 
-    // Capture up
-    if surrounding_cells[0].is_some() && !board.is_empty(surrounding_cells[0].unwrap()) &&
-        !same_color(moved_checker, surrounding_cells[0].unwrap()) {
-        let up_cell = surrounding_cells[0].unwrap();
-        if !same_color(moved_checker, up_cell) && up_cell.y > 0 {
-            let up_up_cell = Position { x: up_cell.x, y: up_cell.y-1 };
-
-            // King capture
-            if is_king(up_cell) {
-                // Four side capture
-                if surrounded(up_cell) {
-                    captured_checkers.push(up_cell);
-                }
-                // Two side capture
-                else if !board.is_king_in_throne() && (same_color(up_up_cell, moved_checker) || board.cell_type(up_up_cell) == C) {
-                    captured_checkers.push(up_cell);
-                }
-            }
-            // Regular checker capture
-            else if same_color(up_up_cell, moved_checker) || is_barrier(board, up_up_cell) {
-                captured_checkers.push(up_cell);
-            }
-        }
+    let neighbour: Bitboard = moved_checker.shift(direction) & enemies;
+    let beyond: Bitboard = neighbour.shift(direction);
+    if beyond.is_empty() {
+        return Bitboard::EMPTY;
     }
 
-    // Capture down
-    if surrounding_cells[1].is_some() && !board.is_empty(surrounding_cells[1].unwrap()) &&
-        !same_color(moved_checker, surrounding_cells[1].unwrap()) {
-        let down_cell = surrounding_cells[1].unwrap();
-        if !same_color(moved_checker, down_cell) && down_cell.y < 8 {
-            let down_down_cell = Position { x: down_cell.x, y: down_cell.y+1 };
-
-            // King capture
-            if is_king(down_cell) {
-                // Four side capture
-                if surrounded(down_cell) {
-                    captured_checkers.push(down_cell);
-                }
-                // Two side capture
-                else if !board.is_king_in_throne() && (same_color(down_down_cell, moved_checker) || board.cell_type(down_down_cell) == C) {
-                    captured_checkers.push(down_cell);
-                }
-            }
-            // Regular checker capture
-            else if same_color(down_down_cell, moved_checker) || is_barrier(board, down_down_cell) {
-                captured_checkers.push(down_cell);
-            }
+    // King capture
+    if !(neighbour & king).is_empty() {
+        let besiegers: Bitboard = board.barriers() | board.black();
+        if rules.king_capture_sides(neighbour.first().unwrap()) > 2 {
+            // Every side closed by black checkers or hostile cells
+            let surrounded = DIRECTIONS.iter().all(|d| {
+                let cell = neighbour.shift(*d);
+                !cell.is_empty() && !(cell & besiegers).is_empty()
+            });
+            return if surrounded { neighbour } else { Bitboard::EMPTY };
+        }
+        // Two side capture
+        if !(beyond & (besiegers | rules.hostile())).is_empty() {
+            return neighbour;
         }
+        return Bitboard::EMPTY;
     }
 
-    // Capture right
-    if surrounding_cells[2].is_some() && !board.is_empty(surrounding_cells[2].unwrap()) &&
-        !same_color(moved_checker, surrounding_cells[2].unwrap()) {
-        let right_cell = surrounding_cells[2].unwrap();
-        if !same_color(moved_checker, right_cell) && right_cell.x < 8 {
-            let right_right_cell = Position { x: right_cell.x+1, y: right_cell.y };
-
-            // King capture
-            if is_king(right_cell) {
-                // Four side capture
-                if surrounded(right_cell) {
-                    captured_checkers.push(right_cell);
-                }
-                // Two side capture
-                else if !board.is_king_in_throne() && (same_color(right_right_cell, moved_checker) || board.cell_type(right_right_cell) == C) {
-                    captured_checkers.push(right_cell);
-                }
-            }
-            // Regular checker capture
-            else if same_color(right_right_cell, moved_checker) || is_barrier(board,right_right_cell) {
-                captured_checkers.push(right_cell);
-            }
-        }
+    // Regular checker capture
+    if !(beyond & (anvils | board.barriers())).is_empty() {
+        return neighbour;
     }
+    Bitboard::EMPTY
+}
 
-    // Capture left
-    if surrounding_cells[3].is_some() && !board.is_empty(surrounding_cells[3].unwrap()) &&
-        !same_color(moved_checker, surrounding_cells[3].unwrap()) {
-        let left_cell = surrounding_cells[3].unwrap();
-        if !same_color(moved_checker, left_cell) && left_cell.x > 0 {
-            let left_left_cell = Position { x: left_cell.x-1, y: left_cell.y };
+// Returns which checkers has been captured by a move
+#[allow(dead_code)]
+pub fn captures(board: &Board, m: &Move) -> Vec<Position> {
+    let moved_checker: Bitboard = Bitboard::from_position(m.to);
+    DIRECTIONS.iter()
+        .filter_map(|direction| capture_in_direction(board, moved_checker, *direction).first())
+        .collect()
+}
 
-            // King capture
-            if is_king(left_cell) {
-                // Four side capture
-                if surrounded(left_cell) {
-                    captured_checkers.push(left_cell);
-                }
-                // Two side capture
-                else if !board.is_king_in_throne() && (same_color(left_left_cell, moved_checker) || board.cell_type(left_left_cell) == C) {
-                    captured_checkers.push(left_cell);
-                }
-            }
-            // Regular checker capture
-            else if same_color(left_left_cell, moved_checker) || is_barrier(board, left_left_cell) {
-                captured_checkers.push(left_cell);
-            }
-        }
-    }
+// Returns the mask of checkers captured by a move
+pub fn capture_mask(board: &Board, m: &Move) -> Bitboard {
+    let moved_checker: Bitboard = Bitboard::from_position(m.to);
+    DIRECTIONS.iter()
+        .fold(Bitboard::EMPTY, |acc, direction| acc | capture_in_direction(board, moved_checker, *direction))
+}
 
-    captured_checkers
+// Returns how many checkers a move would capture on the board before the move
+pub fn capture_count(board: &Board, m: &Move) -> u32 {
+    let mut after: Board = *board;
+    after.move_checker(m);
+    capture_mask(&after, m).count()
 }
 
 // Returns the status of the game
 pub fn game_status(state: &State) -> Status {
     let board = &state.board;
-    let history= &state.history;
+    let history_keys = &state.history_keys;
     let color = &state.color;
 
     let king_cell: Option<Position> = board.king_cell();
@@ -319,31 +246,118 @@ pub fn game_status(state: &State) -> Status {
     }
 
     // King on escape cell
-    if board.cell_type(king_cell.unwrap()) == F && color == WHITE {
+    let escapes: Bitboard = board.rules().escapes;
+    if escapes.contains(king_cell.unwrap()) && color == WHITE {
         return Status::WIN;
     }
-    if board.cell_type(king_cell.unwrap()) == F && color == BLACK {
+    if escapes.contains(king_cell.unwrap()) && color == BLACK {
         return Status::LOSS;
     }
 
-    // TODO optimize
     // No moves possible
-    if legal_moves(state).len() == 0 {
+    if !has_legal_moves(state) {
         return Status::LOSS;
     }
 
-    if history.len() > 3 && history.split_at(history.len()-2).0.contains(board) {
-        return Status::DRAW;
+    if let Some(repetitions) = board.rules().repetition_draw {
+        if history_keys.len() > 3 {
+            let seen = history_keys.split_at(history_keys.len()-2).0.iter().filter(|key| **key == board.key()).count();
+            if seen >= repetitions {
+                return Status::DRAW;
+            }
+        }
     }
 
     Status::ONGOING
 }
 
+// Checks if the king was captured or has escaped, positions without any move to play
+fn game_over(board: &Board) -> bool {
+    board.king_cell().is_none_or(|king| board.rules().escapes.contains(king))
+}
+
+// Number of positions reached after depth moves, counting each path through the move tree.
+// A position where the game is over has no moves, repetitions are ignored.
+pub fn perft(state: &mut State, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if game_over(&state.board) {
+        return 0;
+    }
+    let moves = legal_moves(state);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes: u64 = 0;
+    for m in moves.iter() {
+        let undo = state.make_move(m);
+        nodes += perft(state, depth - 1);
+        state.unmake_move(undo);
+    }
+    nodes
+}
+
+// Perft of the position after each legal move, in move generation order
+pub fn perft_divide(state: &mut State, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 || game_over(&state.board) {
+        return vec![];
+    }
+    legal_moves(state).iter().map(|m| {
+        let undo = state.make_move(m);
+        let nodes = perft(state, depth - 1);
+        state.unmake_move(undo);
+        (*m, nodes)
+    }).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::constants::*;
     use crate::game::{Move, Position, Status, State, Board};
-    use crate::rules::{legal_moves, captures, game_status, obstacles};
+    use crate::rules::{legal_move, legal_moves, captures, game_status, obstacles, perft, perft_divide, camp_cells};
+    use crate::ruleset::{Ruleset, KingCapture, ASHTON, CLASSIC, BRANDUBH, HNEFATAFL};
+    use crate::bitboard::Bitboard;
+
+    // Two camps touching at a corner, the first one bent around b2
+    const TOUCHING_CAMPS_CELLS: &[&str] = &[
+        "CCC....",
+        "C.C....",
+        "...C...",
+        "...C...",
+        ".......",
+        ".......",
+        "......."
+    ];
+
+    const TOUCHING_CAMPS_INITIAL: &[&str] = &[
+        "..B....",
+        "B......",
+        "...B...",
+        ".......",
+        ".......",
+        ".......",
+        "......K"
+    ];
+
+    static TOUCHING_CAMPS: Ruleset = Ruleset {
+        name: "touching camps",
+        size: 7,
+        cells: TOUCHING_CAMPS_CELLS,
+        initial: TOUCHING_CAMPS_INITIAL,
+        area: Bitboard::square(7),
+        camps: Bitboard::from_layout(TOUCHING_CAMPS_CELLS, b'C'),
+        throne: Bitboard::EMPTY,
+        escapes: Bitboard::EMPTY,
+        camps_block: true,
+        hostile_escapes: false,
+        king_only_escapes: false,
+        king_armed: true,
+        king_capture: KingCapture { on_throne: 4, next_to_throne: 3, elsewhere: 2 },
+        black_reenters_camps: false,
+        repetition_draw: None,
+        first_turn: BLACK
+    };
 
     #[test]
     fn test_obstacles() {
@@ -683,6 +697,111 @@ mod tests {
         assert_eq!(moves.len(), 56);
     }
 
+    #[test]
+    fn test_legal_move() {
+        let mut positions = vec![State::init(WHITE.to_string()), State::init(BLACK.to_string()),
+            State::with_rules(BLACK.to_string(), &HNEFATAFL), State::with_rules(WHITE.to_string(), &BRANDUBH)];
+        let mut state = State::init(BLACK.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 2, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 3, 2, 0, 0, 0],
+            [2, 0, 0, 1, 0, 0, 0, 0, 2],
+            [2, 2, 1, 1, 0, 2, 0, 2, 2],
+            [2, 0, 0, 1, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 0, 0, 0, 0]
+        ]);
+        positions.push(state.clone());
+        state.color = WHITE.to_string();
+        positions.push(state);
+
+        for state in positions.iter() {
+            let moves = legal_moves(state);
+            let size = state.board.size();
+            for from in state.board.rules().area.iter() {
+                for to in state.board.rules().area.iter() {
+                    let m = Move { from, to };
+                    assert_eq!(legal_move(state, &m), moves.contains(&m), "{} on a {}x{} board", m, size, size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_camps() {
+        let mut state = State::init(BLACK.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 2, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 3, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 2, 0, 0, 0, 0, 0]
+        ]);
+        let legal = |state: &State, m: &str| legal_move(state, &m.parse().unwrap());
+
+        // Inside each camp, and out of it through its own cells
+        for m in ["e1->d1", "e1->e2", "e1->a1", "e1->i1", "e1->e4",
+                  "a4->a5", "a4->a6", "b5->a5", "a4->a1", "a4->h4",
+                  "i5->h5", "i5->i4", "i5->i1", "i5->f5",
+                  "d9->e9", "d9->f9", "d9->i9", "d9->d6"].iter() {
+            assert!(legal(&state, m), "{}", m);
+        }
+        // Into another camp, across the throne, and white into a camp
+        for m in ["a4->i4", "e1->e5", "e1->e6"].iter() {
+            assert!(!legal(&state, m), "{}", m);
+        }
+        let mut white = state.clone();
+        white.color = WHITE.to_string();
+        assert!(!legal(&white, "e7->e8") && !legal(&white, "e7->e9") && legal(&white, "e7->a7"));
+
+        let moves = legal_moves(&state);
+        for from in ASHTON.area.iter() {
+            for to in ASHTON.area.iter() {
+                let m = Move { from, to };
+                assert_eq!(legal_move(&state, &m), moves.contains(&m), "{}", m);
+            }
+        }
+
+        // No way back once out
+        for m in ["e1->e3", "e7->c7", "b5->c5", "c7->c8"].iter() {
+            state.make_move(&m.parse().unwrap());
+        }
+        for m in ["e3->e2", "e3->e1", "c5->b5", "c5->a5"].iter() {
+            assert!(!legal(&state, m), "{}", m);
+        }
+        assert!(legal(&state, "a4->a5") && legal(&state, "e3->d3"));
+        assert!(legal_moves(&state).iter().all(|m| !ASHTON.camps.contains(m.to) || ASHTON.camps.contains(m.from)));
+
+        // Two camps touching at a corner
+        let state = State::from_notation("2B4/B6/3B3/7/7/7/6K b", &TOUCHING_CAMPS).unwrap();
+        let cells = |cells: &[&str]| cells.iter().fold(Bitboard::EMPTY, |bitboard, cell| bitboard | Bitboard::from_position(cell.parse().unwrap()));
+        let legal = |m: &str| legal_move(&state, &m.parse().unwrap());
+
+        // Each checker keeps to its own camp, not the one touching it
+        assert_eq!(camp_cells(&state, "a2".parse().unwrap()), cells(&["a1", "b1", "c2"]));
+        assert_eq!(camp_cells(&state, "c1".parse().unwrap()), cells(&["a1", "b1", "c2"]));
+        assert_eq!(camp_cells(&state, "d3".parse().unwrap()), cells(&["d4"]));
+        for m in ["a2->a1", "c1->c2", "c1->d1", "a2->b2", "d3->d4", "d3->d5", "d3->c3"].iter() {
+            assert!(legal(m), "{}", m);
+        }
+        // Back into the camp after leaving it, even its own
+        assert!(!legal("a2->c2"));
+
+        let moves = legal_moves(&state);
+        for from in TOUCHING_CAMPS.area.iter() {
+            for to in TOUCHING_CAMPS.area.iter() {
+                let m = Move { from, to };
+                assert_eq!(legal_move(&state, &m), moves.contains(&m), "{}", m);
+            }
+        }
+    }
+
     #[test]
     fn test_game_status() {
         let mut state = State::init(WHITE.to_string());
@@ -698,6 +817,7 @@ mod tests {
             [0, 0, 0, 0, 2, 2, 0, 0, 0]
         ]);
         state.history.push(board);
+        state.history_keys.push(board.key());
         state.board = board;
         let m = Move {
             from: Position {
@@ -713,4 +833,185 @@ mod tests {
         let status = game_status(&state);
         assert_eq!(status, Status::LOSS);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_game_status_repetition() {
+        let mut state = State::init(WHITE.to_string());
+        let white_move = Move { from: Position { x: 4, y: 2 }, to: Position { x: 1, y: 2 } };
+        let white_move_back = Move { from: white_move.to, to: white_move.from };
+        let black_move = Move { from: Position { x: 2, y: 1 }, to: Position { x: 1, y: 1 } };
+        let black_move_back = Move { from: black_move.to, to: black_move.from };
+        state.apply_move(&white_move);
+        state.apply_move(&Move { from: Position { x: 4, y: 1 }, to: Position { x: 2, y: 1 } });
+        let repeated_board = state.board;
+        state.apply_move(&white_move_back);
+        state.apply_move(&black_move);
+        state.apply_move(&white_move);
+        assert_eq!(game_status(&state), Status::ONGOING);
+        state.apply_move(&black_move_back);
+        assert_eq!(state.board, repeated_board);
+        assert_eq!(game_status(&state), Status::DRAW);
+    }
+
+    #[test]
+    fn test_rulesets() {
+        // Next to the throne the king needs black checkers on its three free sides
+        let board = Board::new([
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 0, 2, 3, 2, 0, 0, 2],
+            [2, 2, 0, 0, 0, 0, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        let m = Move { from: Position { x: 6, y: 3 }, to: Position { x: 5, y: 3 } };
+        assert_eq!(captures(&board, &m), vec![]);
+        let board = Board::new([
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [2, 0, 0, 2, 3, 2, 0, 0, 2],
+            [2, 2, 0, 0, 0, 0, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        let m = Move { from: Position { x: 4, y: 1 }, to: Position { x: 4, y: 2 } };
+        assert_eq!(captures(&board, &m), vec![Position { x: 4, y: 3 }]);
+
+        // Any edge cell is an escape in classic Tablut
+        let mut state = State::init(BLACK.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 3, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [2, 2, 0, 0, 0, 0, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 2, 0, 0, 0]
+        ]);
+        assert_eq!(game_status(&state), Status::ONGOING);
+        state.board.set_rules(&CLASSIC);
+        assert_eq!(game_status(&state), Status::LOSS);
+    }
+
+    #[test]
+    fn test_board_sizes() {
+        for rules in [&BRANDUBH, &HNEFATAFL].iter() {
+            for color in [WHITE, BLACK].iter() {
+                let state = State::with_rules(color.to_string(), rules);
+                let moves = legal_moves(&state);
+                assert!(!moves.is_empty());
+                assert!(moves.iter().all(|m| rules.area.contains(m.to) && state.board.is_empty(m.to)));
+                assert!(moves.iter().all(|m| !rules.escapes.contains(m.to)));
+            }
+        }
+        // Attacker moves of the standard openings
+        assert_eq!(legal_moves(&State::with_rules(BLACK.to_string(), &BRANDUBH)).len(), 40);
+        assert_eq!(legal_moves(&State::with_rules(BLACK.to_string(), &HNEFATAFL)).len(), 116);
+
+        // The king escapes to a corner of the 11x11 board
+        let mut state = State::with_rules(WHITE.to_string(), &HNEFATAFL);
+        let mut rows = vec![vec![0u32; 11]; 11];
+        rows[0][3] = B;
+        rows[5][0] = B;
+        rows[10][4] = K;
+        state.board = Board::from_cells(&HNEFATAFL, &rows);
+        let escape = Move { from: Position { x: 4, y: 10 }, to: Position { x: 10, y: 10 } };
+        assert!(legal_moves(&state).contains(&escape));
+        state.make_move(&escape);
+        assert_eq!(game_status(&state), Status::LOSS);
+    }
+
+    #[test]
+    fn test_perft() {
+        // Black to move, the king on e3 may escape to a3
+        let midgame = "4BB3/3B2B2/4K2W1/BW6B/BBWW1WWBB/B3W3B/4W4/4B4/3BBB3 b";
+        let cases: Vec<(State, Vec<u64>)> = vec![
+            (State::with_rules(WHITE.to_string(), &ASHTON), vec![56, 4408, 248616]),
+            (State::from_notation(midgame, &ASHTON).unwrap(), vec![75, 4378, 320515]),
+            // Black opens both
+            (State::with_rules(BLACK.to_string(), &BRANDUBH), vec![40, 960, 39512, 1007392]),
+            (State::with_rules(BLACK.to_string(), &HNEFATAFL), vec![116, 6788, 806344])
+        ];
+        for (state, counts) in cases.iter() {
+            let mut state = state.clone();
+            let notation = state.to_notation();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut state, depth as u32 + 1), *count, "{} at depth {}", notation, depth + 1);
+            }
+            assert_eq!(state.to_notation(), notation);
+            assert_eq!(perft(&mut state, 0), 1);
+            let divide = perft_divide(&mut state, 2);
+            assert_eq!(divide.len() as u64, counts[0]);
+            assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), counts[1]);
+        }
+
+        // Over after the escape
+        let mut state = State::from_notation(midgame, &ASHTON).unwrap();
+        state.make_move(&"a4->a2".parse().unwrap());
+        let divide = perft_divide(&mut state, 2);
+        let (_, nodes) = divide.iter().find(|(m, _)| m.to_string() == "e3->a3").unwrap();
+        assert_eq!(*nodes, 0);
+        state.make_move(&"e3->a3".parse().unwrap());
+        assert_eq!(perft(&mut state, 1), 0);
+        assert!(perft_divide(&mut state, 1).is_empty());
+    }
+}
+#[cfg(test)]
+mod bench {
+    use crate::constants::*;
+    use crate::game::{Board, State};
+    use crate::rules::{legal_moves, captures, capture_mask};
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    #[test]
+    #[ignore]
+    fn bench_move_generation() {
+        let mut state = State::init(BLACK.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 2, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 1, 2, 0, 0, 0],
+            [2, 0, 0, 1, 3, 0, 0, 0, 2],
+            [2, 2, 1, 1, 0, 2, 0, 2, 2],
+            [2, 0, 0, 1, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 0, 0, 0, 0]
+        ]);
+        let iterations = 100_000;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(legal_moves(black_box(&state)));
+        }
+        println!("legal_moves: {:?}/call", start.elapsed() / iterations);
+        let moves = legal_moves(&state);
+        let start = Instant::now();
+        for _ in 0..iterations / 10 {
+            for m in moves.iter() {
+                black_box(captures(black_box(&state.board), m));
+            }
+        }
+        println!("captures: {:?}/call", start.elapsed() / (iterations / 10 * moves.len() as u32));
+        let start = Instant::now();
+        for _ in 0..iterations / 10 {
+            for m in moves.iter() {
+                black_box(capture_mask(black_box(&state.board), m));
+            }
+        }
+        println!("capture_mask: {:?}/call", start.elapsed() / (iterations / 10 * moves.len() as u32));
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(black_box(&state.board).white_cells());
+        }
+        println!("white_cells: {:?}/call", start.elapsed() / iterations);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::bitboard::Bitboard;
+use crate::game::Position;
+use crate::constants::{WHITE, BLACK};
+
+// Number of sides the attackers must close to capture the king, depending on where it stands.
+// With three or four, every free side must be an attacker or a hostile square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KingCapture {
+    pub on_throne: u32,
+    pub next_to_throne: u32,
+    pub elsewhere: u32
+}
+
+// Rules that change between tafl variants
+#[derive(Debug, PartialEq, Eq)]
+pub struct Ruleset {
+    pub name: &'static str,
+    pub size: u32,
+    // Cell types, one row per string: '.' regular, 'C' camp, 'T' throne, 'E' escape
+    pub cells: &'static [&'static str],
+    // Starting checkers, one row per string: 'W' white, 'B' black, 'K' king, '.' empty
+    pub initial: &'static [&'static str],
+    // Every cell of the board
+    pub area: Bitboard,
+    pub camps: Bitboard,
+    pub throne: Bitboard,
+    pub escapes: Bitboard,
+    // Camps can not be entered or crossed and are hostile squares in captures
+    pub camps_block: bool,
+    // Escape cells are hostile squares in captures
+    pub hostile_escapes: bool,
+    // Only the king may stop on escape cells
+    pub king_only_escapes: bool,
+    // The king takes part in captures of black checkers
+    pub king_armed: bool,
+    pub king_capture: KingCapture,
+    // Black checkers may move into camps
+    pub black_reenters_camps: bool,
+    // A position seen this many times before is a draw, None when repetitions never end the game
+    pub repetition_draw: Option<usize>,
+    // Side that moves first from the initial position
+    pub first_turn: &'static str
+}
+
+const ASHTON_CELLS: &[&str] = &[
+    ".EECCCEE.",
+    "E...C...E",
+    "E.......E",
+    "C.......C",
+    "CC..T..CC",
+    "C.......C",
+    "E.......E",
+    "E...C...E",
+    ".EECCCEE."
+];
+
+const TABLUT_INITIAL: &[&str] = &[
+    "...BBB...",
+    "....B....",
+    "....W....",
+    "B...W...B",
+    "BBWWKWWBB",
+    "B...W...B",
+    "....W....",
+    "....B....",
+    "...BBB..."
+];
+
+const CLASSIC_CELLS: &[&str] = &[
+    "EEEEEEEEE",
+    "E.......E",
+    "E.......E",
+    "E.......E",
+    "E...T...E",
+    "E.......E",
+    "E.......E",
+    "E.......E",
+    "EEEEEEEEE"
+];
+
+const BRANDUBH_CELLS: &[&str] = &[
+    "E.....E",
+    ".......",
+    ".......",
+    "...T...",
+    ".......",
+    ".......",
+    "E.....E"
+];
+
+const HNEFATAFL_CELLS: &[&str] = &[
+    "E.........E",
+    "...........",
+    "...........",
+    "...........",
+    "...........",
+    ".....T.....",
+    "...........",
+    "...........",
+    "...........",
+    "...........",
+    "E.........E"
+];
+
+const HNEFATAFL_INITIAL: &[&str] = &[
+    "...BBBBB...",
+    ".....B.....",
+    "...........",
+    "B....W....B",
+    "B...WWW...B",
+    "BB.WWKWW.BB",
+    "B...WWW...B",
+    "B....W....B",
+    "...........",
+    ".....B.....",
+    "...BBBBB..."
+];
+
+const BRANDUBH_INITIAL: &[&str] = &[
+    "...B...",
+    "...B...",
+    "...W...",
+    "BBWKWBB",
+    "...W...",
+    "...B...",
+    "...B..."
+];
+
+// Tablut as played on the university tournament server
+pub const ASHTON: Ruleset = Ruleset {
+    name: "ashton",
+    size: 9,
+    cells: ASHTON_CELLS,
+    initial: TABLUT_INITIAL,
+    area: Bitboard::square(9),
+    camps: Bitboard::from_layout(ASHTON_CELLS, b'C'),
+    throne: Bitboard::from_layout(ASHTON_CELLS, b'T'),
+    escapes: Bitboard::from_layout(ASHTON_CELLS, b'E'),
+    camps_block: true,
+    hostile_escapes: false,
+    king_only_escapes: false,
+    king_armed: true,
+    king_capture: KingCapture { on_throne: 4, next_to_throne: 3, elsewhere: 2 },
+    black_reenters_camps: false,
+    repetition_draw: Some(1),
+    first_turn: WHITE
+};
+
+// Linnaeus' Tablut, the king escapes to any edge cell
+pub const CLASSIC: Ruleset = Ruleset {
+    name: "classic",
+    size: 9,
+    cells: CLASSIC_CELLS,
+    initial: TABLUT_INITIAL,
+    area: Bitboard::square(9),
+    camps: Bitboard::from_layout(CLASSIC_CELLS, b'C'),
+    throne: Bitboard::from_layout(CLASSIC_CELLS, b'T'),
+    escapes: Bitboard::from_layout(CLASSIC_CELLS, b'E'),
+    camps_block: false,
+    hostile_escapes: false,
+    king_only_escapes: false,
+    king_armed: true,
+    king_capture: KingCapture { on_throne: 4, next_to_throne: 3, elsewhere: 2 },
+    black_reenters_camps: true,
+    repetition_draw: Some(2),
+    first_turn: WHITE
+};
+
+// Irish 7x7 variant, the king escapes to a corner and corners are hostile
+pub const BRANDUBH: Ruleset = Ruleset {
+    name: "brandubh",
+    size: 7,
+    cells: BRANDUBH_CELLS,
+    initial: BRANDUBH_INITIAL,
+    area: Bitboard::square(7),
+    camps: Bitboard::from_layout(BRANDUBH_CELLS, b'C'),
+    throne: Bitboard::from_layout(BRANDUBH_CELLS, b'T'),
+    escapes: Bitboard::from_layout(BRANDUBH_CELLS, b'E'),
+    camps_block: false,
+    hostile_escapes: true,
+    king_only_escapes: true,
+    king_armed: true,
+    king_capture: KingCapture { on_throne: 4, next_to_throne: 3, elsewhere: 2 },
+    black_reenters_camps: true,
+    repetition_draw: Some(2),
+    first_turn: BLACK
+};
+
+// Copenhagen Hnefatafl, the king escapes to a corner and is only captured from four sides
+pub const HNEFATAFL: Ruleset = Ruleset {
+    name: "hnefatafl",
+    size: 11,
+    cells: HNEFATAFL_CELLS,
+    initial: HNEFATAFL_INITIAL,
+    area: Bitboard::square(11),
+    camps: Bitboard::from_layout(HNEFATAFL_CELLS, b'C'),
+    throne: Bitboard::from_layout(HNEFATAFL_CELLS, b'T'),
+    escapes: Bitboard::from_layout(HNEFATAFL_CELLS, b'E'),
+    camps_block: false,
+    hostile_escapes: true,
+    king_only_escapes: true,
+    king_armed: true,
+    king_capture: KingCapture { on_throne: 4, next_to_throne: 4, elsewhere: 4 },
+    black_reenters_camps: true,
+    repetition_draw: Some(2),
+    first_turn: BLACK
+};
+
+pub const RULESETS: [&Ruleset; 4] = [&ASHTON, &CLASSIC, &BRANDUBH, &HNEFATAFL];
+
+impl Ruleset {
+    pub fn by_name(name: &str) -> Option<&'static Ruleset> {
+        RULESETS.iter().find(|rules| rules.name == name).copied()
+    }
+
+    // Cells that no checker may stop on or cross, for the given side, cells off the board included
+    pub fn blocked(&self, black: bool) -> Bitboard {
+        if self.camps_block && !(black && self.black_reenters_camps) {
+            self.camps | self.throne | !self.area
+        } else {
+            self.throne | !self.area
+        }
+    }
+
+    // Camp cells connected to the given cell through other camp cells, empty when it is not a camp
+    pub fn camp(&self, cell: Position) -> Bitboard {
+        let mut camp = Bitboard::from_position(cell) & self.camps;
+        loop {
+            let grown = (camp | camp.neighbours()) & self.camps;
+            if grown == camp {
+                return camp;
+            }
+            camp = grown;
+        }
+    }
+
+    // Cells that capture like an enemy checker when empty
+    pub fn hostile(&self) -> Bitboard {
+        let mut hostile = self.throne;
+        if self.camps_block {
+            hostile |= self.camps;
+        }
+        if self.hostile_escapes {
+            hostile |= self.escapes;
+        }
+        hostile
+    }
+
+    // Sides needed to capture the king standing on the given cell
+    pub fn king_capture_sides(&self, king: Position) -> u32 {
+        let king = Bitboard::from_position(king);
+        if !(king & self.throne).is_empty() {
+            self.king_capture.on_throne
+        } else if !(king.neighbours() & self.throne).is_empty() {
+            self.king_capture.next_to_throne
+        } else {
+            self.king_capture.elsewhere
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bitboard::{MAX_SIZE, MIN_SIZE};
+
+    #[test]
+    fn test_ashton_masks() {
+        assert_eq!(ASHTON.camps.count(), 16);
+        assert_eq!(ASHTON.escapes.count(), 16);
+        assert_eq!(ASHTON.throne.first(), Some(Position { x: 4, y: 4 }));
+        assert!(ASHTON.camps.contains(Position { x: 4, y: 1 }));
+        assert!(ASHTON.escapes.contains(Position { x: 0, y: 1 }));
+        assert_eq!(ASHTON.camp(Position { x: 4, y: 1 }).count(), 4);
+        assert!(ASHTON.camp(Position { x: 4, y: 4 }).is_empty());
+    }
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(Ruleset::by_name("classic"), Some(&CLASSIC));
+        assert!(Ruleset::by_name("alea evangelii").is_none());
+        for rules in RULESETS.iter() {
+            assert!(rules.size >= MIN_SIZE && rules.size <= MAX_SIZE);
+            assert_eq!(rules.area.count(), rules.size * rules.size);
+            assert_eq!(rules.cells.len(), rules.size as usize);
+            assert_eq!(rules.initial.len(), rules.size as usize);
+            assert!(rules.cells.iter().chain(rules.initial.iter()).all(|row| row.len() == rules.size as usize));
+        }
+        assert_eq!(CLASSIC.escapes.count(), 32);
+        assert_eq!(HNEFATAFL.escapes.count(), 4);
+        assert_eq!(Bitboard::from_layout(HNEFATAFL.initial, b'B').count(), 24);
+        assert_eq!(Bitboard::from_layout(HNEFATAFL.initial, b'W').count(), 12);
+        assert!(HNEFATAFL.blocked(true).contains(Position { x: 11, y: 0 }));
+        assert!(CLASSIC.camps.is_empty());
+        assert!(ASHTON.hostile().contains(Position { x: 3, y: 0 }));
+        assert!(!CLASSIC.hostile().contains(Position { x: 3, y: 0 }));
+        assert_eq!(ASHTON.king_capture_sides(Position { x: 4, y: 4 }), 4);
+        assert_eq!(ASHTON.king_capture_sides(Position { x: 4, y: 3 }), 3);
+        assert_eq!(ASHTON.king_capture_sides(Position { x: 2, y: 2 }), 2);
+        assert_eq!((ASHTON.first_turn, HNEFATAFL.first_turn), (WHITE, BLACK));
+    }
+}
@@ -1,8 +1,15 @@
 use crate::constants::*;
 use crate::game::{Move, State, Status, Position};
-use crate::rules::{legal_moves, game_status, obstacles, is_barrier, get_opposite_color, is_legal_target_cell};
-use std::cmp::{max, min};
-use std::time::Instant;
+use crate::transposition::{TranspositionTable, Entry, Bound};
+use crate::rules::{legal_moves, legal_moves_into, game_status, obstacles, is_barrier, get_opposite_color, is_legal_target_cell, capture_count};
+use crate::bitboard::{Bitboard, cell_index, CELLS};
+use crate::eval::{EvalParams, Evaluator, EscapeEvaluator, Term};
+use std::cmp::{max, min, Reverse};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::fmt;
 use rand::Rng;
 use log::info;
 
@@ -26,60 +33,119 @@ fn terminal_test(state: &State) -> bool {
     }
 }
 
-pub fn heuristic(state: &State) -> i32 {
-    let board = state.board;
-    let previous_board = if state.history.len() >= 2 {
-        state.history.get(state.history.len() - 2)
-    } else {
-        None
-    };
+// Escape cells the king could reach from the given cell in one move
+fn get_king_escapes(state: &State, king: Position) -> u32 {
+    let mut king_escapes: u32 = 0;
+    let last = state.board.size() - 1;
+    let possible_king_escapes: [Position; 4] = [
+        Position { x: king.x, y: 0 },
+        Position { x: king.x, y: last },
+        Position { x: 0, y: king.y },
+        Position { x: last, y: king.y }
+    ];
+    for cell in possible_king_escapes.iter() {
+        if state.board.cell_type(*cell) == F && !obstacles(state, &Move{ from: king, to: *cell }) {
+            king_escapes += 1;
+        }
+    }
+    king_escapes
+}
 
-    let status = game_status(state);
+// Weight of the king cell, other board sizes are scaled onto the 9x9 weights
+fn king_position_weight(king: Position, size: u32, params: &EvalParams) -> i32 {
+    let scale = |c: u32| ((c * 8 + (size - 1) / 2) / (size - 1)) as usize;
+    params.position_weights[scale(king.y)][scale(king.x)]
+}
 
-    if status == Status::WIN {
-        return if state.color == WHITE { std::i32::MAX } else { std::i32::MIN };
+// Rule that gives the heuristic value on its own, with a description of its condition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shortcut {
+    WinInOneMove(&'static str),
+    WinInTwoMoves(&'static str)
+}
+
+impl Shortcut {
+    pub fn value(self, params: &EvalParams) -> i32 {
+        match self {
+            Shortcut::WinInOneMove(_) => params.win_in_one_move,
+            Shortcut::WinInTwoMoves(_) => params.win_in_two_moves
+        }
     }
-    if status == Status::LOSS {
-        return if state.color == WHITE { std::i32::MIN } else { std::i32::MAX };
+
+    pub fn rule(self) -> &'static str {
+        match self {
+            Shortcut::WinInOneMove(rule) | Shortcut::WinInTwoMoves(rule) => rule
+        }
     }
-    if status == Status::DRAW {
-        return 0;
+}
+
+// What the heuristic weighs in a position where the game goes on
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+    // White checkers and king minus black checkers
+    pub checker_difference: i32,
+    pub king: Position,
+    pub king_escapes: i32,
+    pub king_escapes_in_one_move: i32,
+    pub black_around_king: i32,
+    pub black_diagonal_to_king: i32,
+    pub shortcut: Option<Shortcut>
+}
+
+impl Features {
+    // Weighted terms whose sum is the value when no shortcut fires
+    pub fn terms(&self, size: u32, params: &EvalParams) -> [Term; 7] {
+        [
+            Term { name: "checker difference", value: self.checker_difference, weight: params.checker_difference },
+            Term { name: "checker offset", value: params.checker_offset, weight: params.checker_difference },
+            Term { name: "king escapes", value: self.king_escapes, weight: params.king_escape },
+            Term { name: "king escapes in one move", value: self.king_escapes_in_one_move, weight: params.king_escape_in_one_move },
+            Term { name: "black around king", value: self.black_around_king, weight: -params.black_around_king },
+            Term { name: "black diagonal to king", value: self.black_diagonal_to_king, weight: -params.black_diagonal_to_king },
+            Term { name: "king position", value: 1, weight: king_position_weight(self.king, size, params) }
+        ]
     }
 
+    pub fn value(&self, size: u32, params: &EvalParams) -> i32 {
+        match self.shortcut {
+            Some(shortcut) => shortcut.value(params),
+            None => self.terms(size, params).iter().map(|term| term.contribution()).sum()
+        }
+    }
+}
+
+// Value of a finished game for white, None while it goes on
+pub fn game_over_value(state: &State) -> Option<i32> {
+    match game_status(state) {
+        Status::WIN => Some(if state.color == WHITE { i32::MAX } else { i32::MIN }),
+        Status::LOSS => Some(if state.color == WHITE { i32::MIN } else { i32::MAX }),
+        Status::DRAW => Some(0),
+        Status::ONGOING => None
+    }
+}
+
+pub fn heuristic(state: &State, params: &EvalParams) -> i32 {
+    match game_over_value(state) {
+        Some(value) => value,
+        None => heuristic_features(state).value(state.board.size(), params)
+    }
+}
+
+// Features of a position where the game goes on, the king is on the board
+pub fn heuristic_features(state: &State) -> Features {
+    let board = state.board;
+
     // Checker variation
-    let current_checker_difference = board.white_cells().len() as i32 - board.black_cells().len() as i32 + 7;
+    let current_checker_difference = (board.white() | board.king()).count() as i32 - board.black().count() as i32;
 
     let king: Position = board.king_cell().unwrap();
-    let previous_king: Position = if previous_board.is_some() {
-        previous_board.unwrap().king_cell().unwrap()
-    } else {
-        king
-    };
-    let king_moved: bool = king != previous_king;
     let king_surrounding_cells: [Option<Position>; 4] = board.surrounding_cells(king);
-    let previous_king_surrounding_cells: [Option<Position>; 4] = previous_board.unwrap().surrounding_cells(king);
     let king_surrounding_cells_diagonal: [Option<Position>; 4] = board.surrounding_diagonal_cells(king);
 
     // King position
     let king_in_throne: bool = board.is_king_in_throne();
     let king_next_throne: bool = board.is_king_next_throne();
 
-    fn get_king_escapes(state: &State, king: Position) -> u32 {
-        let mut king_escapes: u32 = 0;
-        let possible_king_escapes: [Position; 4] = [
-            Position { x: king.x, y: 0 },
-            Position { x: king.x, y: 8 },
-            Position { x: 0, y: king.y },
-            Position { x: 8, y: king.y }
-        ];
-        for cell in possible_king_escapes.iter() {
-            if state.board.cell_type(*cell) == F && !obstacles(state, &Move{ from: king, to: *cell }) {
-                king_escapes += 1;
-            }
-        }
-        king_escapes
-    }
-
     // King escapes
     let king_escapes: i32 = get_king_escapes(state, king) as i32;
 
@@ -121,17 +187,10 @@ pub fn heuristic(state: &State) -> i32 {
     // Black checkers in respect to king
     let black_checkers_around_king = king_surrounding_cells.iter()
         .fold(0, |acc, cell| if cell.is_some() && board.cell_content(cell.unwrap()) == B { acc + 1} else { acc });
-    let previous_black_checkers_around_king = if previous_board.is_some() {
-        previous_king_surrounding_cells.iter()
-            .fold(0, |acc, cell| if cell.is_some() && previous_board.unwrap().cell_content(cell.unwrap()) == B { acc + 1} else { acc })
-    } else {
-        black_checkers_around_king
-    };
-    let black_checkers_around_king_changed: bool = black_checkers_around_king != previous_black_checkers_around_king;
     let black_checkers_around_king_diagonal = king_surrounding_cells_diagonal.iter()
         .fold(0, |acc, cell| if cell.is_some() && board.cell_content(cell.unwrap()) == B { acc + 1} else { acc });
 
-    let mut black_checkers_around_king_in_one_move: Vec<Position> = vec![];
+    let mut black_checkers_around_king_in_one_move: Bitboard = Bitboard::EMPTY;
     let mut up: Option<Position> = king_surrounding_cells[0];
     let mut down: Option<Position> = king_surrounding_cells[1];
     let mut right: Option<Position> = king_surrounding_cells[2];
@@ -144,11 +203,11 @@ pub fn heuristic(state: &State) -> i32 {
                 if state.board.is_empty(up.unwrap()) {
                     continue;
                 }
-                if state.board.cell_color(up.unwrap()).unwrap() == WHITE {
+                if state.board.cell_content(up.unwrap()) != B {
                     break;
                 } else {
-                    if !black_checkers_around_king_in_one_move.contains(&up.unwrap()) {
-                        black_checkers_around_king_in_one_move.push(up.unwrap());
+                    if !black_checkers_around_king_in_one_move.contains(up.unwrap()) {
+                        black_checkers_around_king_in_one_move.set(up.unwrap());
                     }
                     break
                 }
@@ -165,11 +224,11 @@ pub fn heuristic(state: &State) -> i32 {
                 if state.board.is_empty(down.unwrap()) {
                     continue;
                 }
-                if state.board.cell_color(down.unwrap()).unwrap() == WHITE {
+                if state.board.cell_content(down.unwrap()) != B {
                     break;
                 } else {
-                    if !black_checkers_around_king_in_one_move.contains(&down.unwrap()) {
-                        black_checkers_around_king_in_one_move.push(down.unwrap());
+                    if !black_checkers_around_king_in_one_move.contains(down.unwrap()) {
+                        black_checkers_around_king_in_one_move.set(down.unwrap());
                     }
                     break
                 }
@@ -186,11 +245,11 @@ pub fn heuristic(state: &State) -> i32 {
                 if state.board.is_empty(right.unwrap()) {
                     continue;
                 }
-                if state.board.cell_color(right.unwrap()).unwrap() == WHITE {
+                if state.board.cell_content(right.unwrap()) != B {
                     break;
                 } else {
-                    if !black_checkers_around_king_in_one_move.contains(&right.unwrap()) {
-                        black_checkers_around_king_in_one_move.push(right.unwrap());
+                    if !black_checkers_around_king_in_one_move.contains(right.unwrap()) {
+                        black_checkers_around_king_in_one_move.set(right.unwrap());
                     }
                     break
                 }
@@ -207,11 +266,11 @@ pub fn heuristic(state: &State) -> i32 {
                 if state.board.is_empty(left.unwrap()) {
                     continue;
                 }
-                if state.board.cell_color(left.unwrap()).unwrap() == WHITE {
+                if state.board.cell_content(left.unwrap()) != B {
                     break;
                 } else {
-                    if !black_checkers_around_king_in_one_move.contains(&left.unwrap()) {
-                        black_checkers_around_king_in_one_move.push(left.unwrap());
+                    if !black_checkers_around_king_in_one_move.contains(left.unwrap()) {
+                        black_checkers_around_king_in_one_move.set(left.unwrap());
                     }
                     break
                 }
@@ -221,85 +280,45 @@ pub fn heuristic(state: &State) -> i32 {
         }
     }
 
-    // Barriers in respect to king
-    let barriers_around_king: u32 = king_surrounding_cells.iter().fold(0, |acc, cell| if cell.is_some() && is_barrier(&state.board, cell.unwrap()){ acc } else { acc });
-
-    let position_weights: [[i32; 9]; 9] = [
-        [0,  0,  0,  0,  0,  0,  0,  0,  0],
-        [0,  5, 10,  0,  0,  0, 10,  5,  0],
-        [0,  5, 10,  5,  5,  5, 10,  5,  0],
-        [0,  0,  0,  0,  0,  0,  0,  0,  0],
-        [0,  0,  5,  0, -10,  0,  5,  0,  0],
-        [0,  0,  0,  0,  0,  0,  0,  0,  0],
-        [0,  5, 10,  5,  5,  5, 10,  5,  0],
-        [0,  5, 10,  0,  0,  0, 10,  5,  0],
-        [0,  0,  0,  0,  0,  0,  0,  0,  0],
-    ];
+    // Shortcut rules, the first one that holds gives the value
+    let shortcut = || -> Option<Shortcut> {
+        // WINNING IN ONE MOVE
+        if king_escapes >= 2 {
+            return Some(Shortcut::WinInOneMove("king sees two escapes"));
+        }
 
-    // WINNING IN ONE MOVE
-    if king_escapes >= 2 && (barriers_around_king == 0 || black_checkers_around_king <= 1) {
-        return 10000;
-    }
+        // WINNING IN TWO MOVES
+        if king_escapes_in_one_move >= 2 && king_in_throne && black_checkers_around_king <= 3 && black_checkers_around_king_in_one_move.is_empty() {
+            return Some(Shortcut::WinInTwoMoves("king on the throne reaches two escapes, at most 3 black neighbours, no capture threat"));
+        }
+        if king_escapes_in_one_move >= 2 && king_in_throne && black_checkers_around_king <= 2 {
+            return Some(Shortcut::WinInTwoMoves("king on the throne reaches two escapes, at most 2 black neighbours"));
+        }
+        if king_escapes_in_one_move >= 2 && king_next_throne && black_checkers_around_king <= 2 && black_checkers_around_king_in_one_move.is_empty() {
+            return Some(Shortcut::WinInTwoMoves("king next to the throne reaches two escapes, at most 2 black neighbours, no capture threat"));
+        }
+        if king_escapes_in_one_move >= 2 && king_next_throne && black_checkers_around_king <= 1 {
+            return Some(Shortcut::WinInTwoMoves("king next to the throne reaches two escapes, at most 1 black neighbour"));
+        }
+        if king_escapes_in_one_move >= 2 && !king_in_throne && !king_next_throne && black_checkers_around_king <= 1 && black_checkers_around_king_in_one_move.is_empty() {
+            return Some(Shortcut::WinInTwoMoves("king away from the throne reaches two escapes, at most 1 black neighbour, no capture threat"));
+        }
+        if king_escapes_in_one_move >= 2 && !king_in_throne && !king_next_throne && black_checkers_around_king == 0 {
+            return Some(Shortcut::WinInTwoMoves("king away from the throne reaches two escapes, no black neighbour"));
+        }
 
-    // WINNING IN TWO MOVES
-    if king_escapes_in_one_move >= 2 && king_in_throne && black_checkers_around_king <= 3 && black_checkers_around_king_in_one_move.len() == 0 {
-        return 5000;
-    }
-    if king_escapes_in_one_move >= 2 && king_in_throne && black_checkers_around_king <= 2 {
-        return 5000;
-    }
-    if king_escapes_in_one_move >= 2 && king_next_throne && black_checkers_around_king <= 2 && black_checkers_around_king_in_one_move.len() == 0 {
-        return 5000;
-    }
-    if king_escapes_in_one_move >= 2 && king_next_throne && black_checkers_around_king <= 1 {
-        return 5000;
-    }
-    if king_escapes_in_one_move >= 2 && !king_in_throne && !king_next_throne && black_checkers_around_king <= 1 && black_checkers_around_king_in_one_move.len() == 0 {
-        return 5000;
-    }
-    if king_escapes_in_one_move >= 2 && !king_in_throne && !king_next_throne && black_checkers_around_king == 0 {
-        return 5000;
-    }
+        None
+    };
 
-    // LOSING IN ONE MOVE
-    if king_moved && !king_in_throne && !king_next_throne && barriers_around_king > 0 && black_checkers_around_king_in_one_move.len() > 0 {
-        return -10000;
-    }
-    if king_moved && !king_in_throne && !king_next_throne && !king_moved && barriers_around_king == 0 && black_checkers_around_king_changed && black_checkers_around_king >= 1 && black_checkers_around_king_in_one_move.len() >= 1 {
-        return -10000;
-    }
-    if king_moved && king_next_throne && !king_moved && black_checkers_around_king_changed && black_checkers_around_king >= 2 && black_checkers_around_king_in_one_move.len() >= 1 {
-        return -10000;
+    Features {
+        checker_difference: current_checker_difference,
+        king,
+        king_escapes,
+        king_escapes_in_one_move: king_escapes_in_one_move as i32,
+        black_around_king: black_checkers_around_king,
+        black_diagonal_to_king: black_checkers_around_king_diagonal,
+        shortcut: shortcut()
     }
-    if king_moved && king_in_throne && !king_moved && black_checkers_around_king_changed && black_checkers_around_king >= 3 && black_checkers_around_king_in_one_move.len() >= 1 {
-        return -10000;
-    }
-
-    // Value calculation
-    let mut value: i32 = 0;
-
-    // Checkers variation
-    value += current_checker_difference * 25;
-
-    // King escapes
-    value += king_escapes * 70;
-
-    // King escapes in one move
-    value += king_escapes_in_one_move as i32 * 35;
-
-    // Barriers around king
-    value -= barriers_around_king as i32 * 5;
-
-    // Black checkers around king
-    value -= black_checkers_around_king * 10;
-
-    // Black checker around king diagonal
-    value -= black_checkers_around_king_diagonal * 10;
-
-    // Position weights
-    value += position_weights[king.y as usize][king.x as usize];
-
-    value
 }
 
 #[allow(dead_code)]
@@ -314,7 +333,7 @@ pub fn alpha_beta_search(state: &State, depth: u32) -> (Option<Move>, i32) {
         let mut a = alpha;
         let b = beta;
         if depth == 0 || terminal_test(state) {
-            return heuristic(state);
+            return heuristic(state, &EvalParams::default());
         }
         let mut value = std::i32::MIN;
         for action in actions(state) {
@@ -331,7 +350,7 @@ pub fn alpha_beta_search(state: &State, depth: u32) -> (Option<Move>, i32) {
         let a = alpha;
         let mut b = beta;
         if depth == 0 || terminal_test(state) {
-            return heuristic(state);
+            return heuristic(state, &EvalParams::default());
         }
         let mut value = std::i32::MAX;
         for action in actions(state) {
@@ -365,115 +384,658 @@ pub fn alpha_beta_search(state: &State, depth: u32) -> (Option<Move>, i32) {
     return if state.color == WHITE { ( best_action, alpha) } else { ( best_action, beta ) };
 }
 
-pub fn time_bound_alpha_beta_search(state: &State, depth: u32, end_instant: Instant) -> (Option<Move>, i32, bool) {
+// Search features that can be switched off, to measure what each one is worth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    // Sort moves with captures, killers and history, not only the transposition table move
+    pub move_ordering: bool,
+    // Extend leaves with captures and king escape or threat moves
+    pub quiescence: bool,
+    // Start every iteration with a narrow window around the previous score
+    pub aspiration: bool,
+    // Search the moves after the first one with a zero window
+    pub pvs: bool,
+    // Let the opponent move twice and cut off if the side to move still reaches beta
+    pub null_move: bool,
+    // Search late quiet moves at a reduced depth first
+    pub late_move_reductions: bool,
+    // Return the iteration with the best score for the side to move, not the deepest one
+    pub best_iteration: bool
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            move_ordering: true,
+            quiescence: true,
+            aspiration: true,
+            pvs: true,
+            null_move: true,
+            late_move_reductions: true,
+            best_iteration: true
+        }
+    }
+}
+
+// Search data that survives between iterations and turns
+pub struct SearchContext {
+    // Shared with the helper threads of a parallel search
+    pub tt: Arc<TranspositionTable>,
+    // Search threads, the extra ones are Lazy SMP helpers
+    pub threads: usize,
+    // Raised when the main thread is done so that the helpers return
+    stop: Arc<AtomicBool>,
+    pub config: SearchConfig,
+    // Evaluation of the leaves and its weights
+    pub evaluator: &'static dyn Evaluator,
+    pub eval: EvalParams,
+    // Two quiet moves per ply that caused a cutoff
+    killers: Vec<[Option<Move>; 2]>,
+    // Cutoff score of each quiet move, indexed by origin and destination cell
+    history: Vec<u32>,
+    // Nodes visited since the context was created
+    pub nodes: u64,
+    // Triangular table, pv[ply] is the best line found from that ply
+    pv: Vec<Vec<Move>>,
+    // Reusable move buffers, one per ply, with the ordering key of each move
+    move_lists: Vec<Vec<(u64, Move)>>,
+    generated: Vec<Move>
+}
+
+impl SearchContext {
+    pub fn new(tt_size_mb: usize) -> SearchContext {
+        SearchContext::with_table(Arc::new(TranspositionTable::new(tt_size_mb)), Arc::new(AtomicBool::new(false)))
+    }
+
+    fn with_table(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> SearchContext {
+        SearchContext {
+            tt,
+            threads: 1,
+            stop,
+            config: SearchConfig::default(),
+            evaluator: &EscapeEvaluator,
+            eval: EvalParams::default(),
+            killers: vec![],
+            history: vec![0; (CELLS * CELLS) as usize],
+            nodes: 0,
+            pv: vec![],
+            move_lists: vec![],
+            generated: Vec::with_capacity(128)
+        }
+    }
+
+    // Context of a helper thread, with its own ordering tables and the shared table and stop flag
+    fn helper(&self) -> SearchContext {
+        let mut helper = SearchContext::with_table(Arc::clone(&self.tt), Arc::clone(&self.stop));
+        helper.config = self.config;
+        helper.evaluator = self.evaluator;
+        helper.eval = self.eval;
+        helper
+    }
+
+    fn out_of_time(&self, end_instant: Instant) -> bool {
+        self.stop.load(Ordering::Relaxed) || Instant::now() >= end_instant
+    }
+
+    // Best line found by the last completed search
+    pub fn principal_variation(&self) -> &[Move] {
+        self.pv.first().map_or(&[], |line| line.as_slice())
+    }
+
+    fn clear_pv(&mut self, ply: usize) {
+        if self.pv.len() < ply + 2 {
+            self.pv.resize(ply + 2, vec![]);
+        }
+        self.pv[ply].clear();
+    }
+
+    // The line of a ply becomes the move followed by the line of the next ply
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(m);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+
+    // Remembers a quiet move that caused a beta cutoff
+    fn update_cutoff(&mut self, state: &State, m: Move, depth: u32, ply: usize) {
+        if !self.config.move_ordering || capture_count(&state.board, &m) > 0 {
+            return;
+        }
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None, None]);
+        }
+        if self.killers[ply][0] != Some(m) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(m);
+        }
+        let index = cell_index(m.from) * CELLS as usize + cell_index(m.to);
+        self.history[index] = self.history[index].saturating_add(depth * depth);
+    }
+
+    // Gives back the move buffer of a ply taken by ordered_actions
+    fn release_moves(&mut self, ply: usize, moves: Vec<(u64, Move)>) {
+        self.move_lists[ply] = moves;
+    }
+}
+
+// Bound of a score found with the given window
+fn bound(value: i32, alpha: i32, beta: i32) -> Bound {
+    if value <= alpha {
+        Bound::Upper
+    } else if value >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    }
+}
+
+// Legal moves with the transposition table move first, then captures, killers and
+// the rest by history score. Moves with the same score keep the board-scan order.
+// The returned buffer belongs to the ply and must be given back with SearchContext::release_moves.
+fn ordered_actions(state: &State, tt_move: Option<Move>, ply: usize, context: &mut SearchContext) -> Vec<(u64, Move)> {
+    if context.move_lists.len() <= ply {
+        context.move_lists.resize(ply + 1, vec![]);
+    }
+    let mut moves = std::mem::take(&mut context.move_lists[ply]);
+    let mut generated = std::mem::take(&mut context.generated);
+    legal_moves_into(state, &mut generated);
+    moves.clear();
+
+    let killers = context.killers.get(ply).copied().unwrap_or([None, None]);
+    for (i, m) in generated.iter().enumerate() {
+        let captures = if context.config.move_ordering { capture_count(&state.board, m) } else { 0 };
+        let score: u64 = if Some(*m) == tt_move {
+            4 << 32
+        } else if !context.config.move_ordering {
+            0
+        } else if captures > 0 {
+            (3 << 32) + captures as u64
+        } else if Some(*m) == killers[0] {
+            2 << 32
+        } else if Some(*m) == killers[1] {
+            1 << 32
+        } else {
+            context.history[cell_index(m.from) * CELLS as usize + cell_index(m.to)] as u64
+        };
+        // The low bits keep the generation order between equal scores
+        moves.push(((score << 16) | (0xffff - i as u64), *m));
+    }
+    context.generated = generated;
+    moves.sort_unstable_by_key(|&(key, _)| Reverse(key));
+    moves
+}
+
+// Captures and king moves that escape or open a line to an escape, most promising first
+fn noisy_actions(state: &mut State, ply: usize, context: &mut SearchContext) -> Vec<(u64, Move)> {
+    if context.move_lists.len() <= ply {
+        context.move_lists.resize(ply + 1, vec![]);
+    }
+    let mut moves = std::mem::take(&mut context.move_lists[ply]);
+    let mut generated = std::mem::take(&mut context.generated);
+    legal_moves_into(state, &mut generated);
+    moves.clear();
+
+    let king = state.board.king();
+    let escapes = state.board.rules().escapes;
+    for (i, m) in generated.iter().enumerate() {
+        let captures = capture_count(&state.board, m) as u64;
+        let score: u64 = if king.contains(m.from) && escapes.contains(m.to) {
+            3 << 8
+        } else if captures > 0 {
+            (2 << 8) + captures
+        } else if king.contains(m.from) {
+            let undo = state.make_move(m);
+            let threat = get_king_escapes(state, m.to) > 0;
+            state.unmake_move(undo);
+            if threat { 1 << 8 } else { continue }
+        } else {
+            continue
+        };
+        moves.push(((score << 16) | (0xffff - i as u64), *m));
+    }
+    context.generated = generated;
+    moves.sort_unstable_by_key(|&(key, _)| Reverse(key));
+    moves
+}
+
+// Searches noisy moves from a leaf until the position is quiet, standing pat on the
+// heuristic. Every node uses one unit of the budget shared by the whole leaf.
+fn quiescence(state: &mut State, mut alpha: i32, mut beta: i32, ply: usize, budget: &mut u32, context: &mut SearchContext) -> i32 {
+    context.nodes += 1;
+    let stand_pat = context.evaluator.evaluate(state, &context.eval);
+    if *budget == 0 || terminal_test(state) {
+        return stand_pat;
+    }
+    let white_to_move = state.color == WHITE;
+    if white_to_move {
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = max(alpha, stand_pat);
+    } else {
+        if stand_pat <= alpha {
+            return stand_pat;
+        }
+        beta = min(beta, stand_pat);
+    }
+
+    let mut best_value = stand_pat;
+    let moves = noisy_actions(state, ply, context);
+    for &(_, action) in moves.iter() {
+        if *budget == 0 {
+            break;
+        }
+        *budget -= 1;
+        let undo = state.make_move(&action);
+        let value = quiescence(state, alpha, beta, ply + 1, budget, context);
+        state.unmake_move(undo);
+        if white_to_move {
+            best_value = max(best_value, value);
+            alpha = max(alpha, value);
+        } else {
+            best_value = min(best_value, value);
+            beta = min(beta, value);
+        }
+        if beta <= alpha {
+            break;
+        }
+    }
+    context.release_moves(ply, moves);
+    best_value
+}
+
+// The board is unchanged by the last ply only after a null move
+fn after_null_move(state: &State) -> bool {
+    state.history.last() == Some(&state.board)
+}
+
+// No null move near the leaves, right after another one, or when the side to move has so
+// few moves that passing might be better than all of them (zugzwang)
+fn null_move_allowed(state: &State, depth: u32, context: &mut SearchContext) -> bool {
+    if !context.config.null_move || depth < NULL_MOVE_MIN_DEPTH || after_null_move(state) {
+        return false;
+    }
+    let mut generated = std::mem::take(&mut context.generated);
+    legal_moves_into(state, &mut generated);
+    let allowed = generated.len() >= NULL_MOVE_MIN_MOVES;
+    context.generated = generated;
+    allowed
+}
+
+// Plies removed from the first search of a move: late quiet moves that are not the table
+// move nor a killer, and that do not move the king, are searched one ply shallower
+fn late_move_reduction(state: &State, m: &Move, order: u64, index: usize, depth: u32, context: &SearchContext) -> u32 {
+    if context.config.late_move_reductions && index >= LMR_MIN_MOVE_INDEX && depth >= LMR_MIN_DEPTH &&
+        (order >> 16) < 1 << 32 && !state.board.king().contains(m.from) && capture_count(&state.board, m) == 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Alpha-beta search of the moves of the state within the alpha..beta window. The score is
+// only a bound when it falls outside of the window.
+pub fn time_bound_alpha_beta_window_search(state: &State, depth: u32, mut alpha: i32, mut beta: i32, end_instant: Instant, context: &mut SearchContext) -> (Option<Move>, i32, bool) {
 
-    fn max_value(state: &State, mut alpha: i32, beta: i32, depth: u32, end_instant: Instant) -> (i32, bool) {
-        if Instant::now() >= end_instant {
+    fn max_value(state: &mut State, mut alpha: i32, mut beta: i32, depth: u32, ply: usize, end_instant: Instant, context: &mut SearchContext) -> (i32, bool) {
+        if context.out_of_time(end_instant) {
             return (0, false);
         }
-        if depth == 0 || terminal_test(state) {
-            return (heuristic(state), true);
+        context.nodes += 1;
+        context.clear_pv(ply);
+        let key = state.board.key();
+        let entry = context.tt.probe(key);
+        if let Some(entry) = entry {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => alpha = beta,
+                    Bound::Lower => alpha = max(alpha, entry.score),
+                    Bound::Upper => beta = min(beta, entry.score)
+                }
+                if beta <= alpha {
+                    if let Some(m) = entry.best_move {
+                        context.pv[ply].push(m);
+                    }
+                    return (entry.score, true);
+                }
+            }
         }
+        if terminal_test(state) {
+            return (context.evaluator.evaluate(state, &context.eval), true);
+        }
+        if depth == 0 {
+            if !context.config.quiescence {
+                return (context.evaluator.evaluate(state, &context.eval), true);
+            }
+            let mut budget = QUIESCENCE_NODE_LIMIT;
+            return (quiescence(state, alpha, beta, ply, &mut budget, context), true);
+        }
+        if context.evaluator.evaluate(state, &context.eval) >= beta && null_move_allowed(state, depth, context) {
+            let undo = state.make_null_move();
+            let result = min_value(state, beta.saturating_sub(1), beta, depth.saturating_sub(1 + NULL_MOVE_REDUCTION), ply + 1, end_instant, context);
+            state.unmake_move(undo);
+            if !result.1 {
+                return result;
+            }
+            if result.0 >= beta {
+                return (beta, true);
+            }
+        }
+        let (alpha_start, beta_start) = (alpha, beta);
         let mut best_value = std::i32::MIN;
+        let mut best_move = None;
         let mut completed = true;
 
-        for action in actions(&state) {
-            let result = min_value(&result(&state, &action), alpha, beta, depth - 1, end_instant);
+        let moves = ordered_actions(state, entry.and_then(|e| e.best_move), ply, context);
+        for (i, &(order, action)) in moves.iter().enumerate() {
+            let reduction = late_move_reduction(state, &action, order, i, depth, context);
+            let undo = state.make_move(&action);
+            let mut result = None;
+            // A late move that fails low at the reduced depth is not searched again
+            if reduction > 0 {
+                let reduced = min_value(state, alpha, alpha.saturating_add(1), depth - 1 - reduction, ply + 1, end_instant, context);
+                if !reduced.1 || reduced.0 <= alpha {
+                    result = Some(reduced);
+                }
+            }
+            // After the first move a zero window checks that the move is no better,
+            // and the full window is searched again only if it is
+            if result.is_none() && i > 0 && context.config.pvs {
+                let scout = min_value(state, alpha, alpha.saturating_add(1), depth - 1, ply + 1, end_instant, context);
+                if !scout.1 || scout.0 <= alpha || scout.0 >= beta {
+                    result = Some(scout);
+                }
+            }
+            let result = match result {
+                Some(result) => result,
+                None => min_value(state, alpha, beta, depth - 1, ply + 1, end_instant, context)
+            };
+            state.unmake_move(undo);
             let value = result.0;
             completed = result.1;
-            best_value = max(best_value, value);
+            if completed && (value > best_value || best_move.is_none()) {
+                best_value = value;
+                best_move = Some(action);
+                context.update_pv(ply, action);
+            }
             alpha = max(alpha, value);
-            if beta <= alpha || !completed {
+            if !completed {
+                break;
+            }
+            if beta <= alpha {
+                context.update_cutoff(state, action, depth, ply);
                 break;
             }
         }
-        return (best_value, completed);
-    };
+        context.release_moves(ply, moves);
+        if completed {
+            context.tt.store(Entry { key, depth, bound: bound(best_value, alpha_start, beta_start), score: best_value, best_move });
+        }
+        (best_value, completed)
+    }
 
-    fn min_value(state: &State, alpha: i32, mut beta: i32, depth: u32, end_instant: Instant) -> (i32, bool) {
-        if Instant::now() >= end_instant {
+    fn min_value(state: &mut State, mut alpha: i32, mut beta: i32, depth: u32, ply: usize, end_instant: Instant, context: &mut SearchContext) -> (i32, bool) {
+        if context.out_of_time(end_instant) {
             return (0, false);
         }
-        if depth == 0 || terminal_test(state) {
-            return (heuristic(state), true);
+        context.nodes += 1;
+        context.clear_pv(ply);
+        let key = state.board.key();
+        let entry = context.tt.probe(key);
+        if let Some(entry) = entry {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => alpha = beta,
+                    Bound::Lower => alpha = max(alpha, entry.score),
+                    Bound::Upper => beta = min(beta, entry.score)
+                }
+                if beta <= alpha {
+                    if let Some(m) = entry.best_move {
+                        context.pv[ply].push(m);
+                    }
+                    return (entry.score, true);
+                }
+            }
+        }
+        if terminal_test(state) {
+            return (context.evaluator.evaluate(state, &context.eval), true);
+        }
+        if depth == 0 {
+            if !context.config.quiescence {
+                return (context.evaluator.evaluate(state, &context.eval), true);
+            }
+            let mut budget = QUIESCENCE_NODE_LIMIT;
+            return (quiescence(state, alpha, beta, ply, &mut budget, context), true);
         }
+        if context.evaluator.evaluate(state, &context.eval) <= alpha && null_move_allowed(state, depth, context) {
+            let undo = state.make_null_move();
+            let result = max_value(state, alpha, alpha.saturating_add(1), depth.saturating_sub(1 + NULL_MOVE_REDUCTION), ply + 1, end_instant, context);
+            state.unmake_move(undo);
+            if !result.1 {
+                return result;
+            }
+            if result.0 <= alpha {
+                return (alpha, true);
+            }
+        }
+        let (alpha_start, beta_start) = (alpha, beta);
         let mut best_value = std::i32::MAX;
+        let mut best_move = None;
         let mut completed = true;
 
-        for action in actions(&state) {
-            let result = max_value(&result(&state, &action), alpha, beta, depth - 1, end_instant);
+        let moves = ordered_actions(state, entry.and_then(|e| e.best_move), ply, context);
+        for (i, &(order, action)) in moves.iter().enumerate() {
+            let reduction = late_move_reduction(state, &action, order, i, depth, context);
+            let undo = state.make_move(&action);
+            let mut result = None;
+            if reduction > 0 {
+                let reduced = max_value(state, beta.saturating_sub(1), beta, depth - 1 - reduction, ply + 1, end_instant, context);
+                if !reduced.1 || reduced.0 >= beta {
+                    result = Some(reduced);
+                }
+            }
+            if result.is_none() && i > 0 && context.config.pvs {
+                let scout = max_value(state, beta.saturating_sub(1), beta, depth - 1, ply + 1, end_instant, context);
+                if !scout.1 || scout.0 >= beta || scout.0 <= alpha {
+                    result = Some(scout);
+                }
+            }
+            let result = match result {
+                Some(result) => result,
+                None => max_value(state, alpha, beta, depth - 1, ply + 1, end_instant, context)
+            };
+            state.unmake_move(undo);
             let value = result.0;
             completed = result.1;
-            best_value = min(best_value, value);
+            if completed && (value < best_value || best_move.is_none()) {
+                best_value = value;
+                best_move = Some(action);
+                context.update_pv(ply, action);
+            }
             beta = min(beta, value);
-            if beta <= alpha || !completed {
+            if !completed {
+                break;
+            }
+            if beta <= alpha {
+                context.update_cutoff(state, action, depth, ply);
                 break;
             }
         }
-        return (best_value, completed);
-    };
+        context.release_moves(ply, moves);
+        if completed {
+            context.tt.store(Entry { key, depth, bound: bound(best_value, alpha_start, beta_start), score: best_value, best_move });
+        }
+        (best_value, completed)
+    }
 
+    // The whole search runs on this copy with make and unmake
+    let mut state: State = state.clone();
+    let (alpha_start, beta_start) = (alpha, beta);
     let mut best_action = None;
-    let mut alpha = std::i32::MIN;
-    let mut beta = std::i32::MAX;
     let mut completed = true;
-    for action in actions(state) {
-        if state.color == WHITE {
-            let result = min_value(&result(state, &action), alpha, beta, depth, end_instant);
+    let tt_move = context.tt.probe(state.board.key()).and_then(|e| e.best_move);
+    context.clear_pv(0);
+    let white_to_move = state.color == WHITE;
+    let moves = ordered_actions(&state, tt_move, 0, context);
+    for (i, &(_, action)) in moves.iter().enumerate() {
+        let undo = state.make_move(&action);
+        let scout = i > 0 && context.config.pvs;
+        if white_to_move {
+            let mut result = if scout {
+                min_value(&mut state, alpha, alpha.saturating_add(1), depth, 1, end_instant, context)
+            } else {
+                min_value(&mut state, alpha, beta, depth, 1, end_instant, context)
+            };
+            if scout && result.1 && result.0 > alpha && result.0 < beta {
+                result = min_value(&mut state, alpha, beta, depth, 1, end_instant, context);
+            }
+            state.unmake_move(undo);
             let value = result.0;
             completed = result.1;
             if value > alpha || best_action.is_none() {
                 alpha = value;
                 best_action = Some(action);
+                context.update_pv(0, action);
             }
         } else {
-            let result = max_value(&result(state, &action), alpha, beta, depth, end_instant);
+            let mut result = if scout {
+                max_value(&mut state, beta.saturating_sub(1), beta, depth, 1, end_instant, context)
+            } else {
+                max_value(&mut state, alpha, beta, depth, 1, end_instant, context)
+            };
+            if scout && result.1 && result.0 < beta && result.0 > alpha {
+                result = max_value(&mut state, alpha, beta, depth, 1, end_instant, context);
+            }
+            state.unmake_move(undo);
             let value = result.0;
             completed = result.1;
             if value < beta || best_action.is_none() {
                 beta = value;
                 best_action = Some(action);
+                context.update_pv(0, action);
             }
         }
-        if Instant::now() >= end_instant {
+        if context.out_of_time(end_instant) {
             completed = false;
             break;
         }
+        // Outside of the aspiration window, the caller searches again with a wider one
+        if beta <= alpha {
+            break;
+        }
+    }
+    context.release_moves(0, moves);
+    let value = if white_to_move { alpha } else { beta };
+    // Children are searched at the full depth, so the root counts one more
+    if completed {
+        context.tt.store(Entry { key: state.board.key(), depth: depth + 1, bound: bound(value, alpha_start, beta_start), score: value, best_move: best_action });
+    }
+    (best_action, value, completed)
+}
+
+pub fn time_bound_alpha_beta_search(state: &State, depth: u32, end_instant: Instant, context: &mut SearchContext) -> (Option<Move>, i32, bool) {
+    time_bound_alpha_beta_window_search(state, depth, i32::MIN, i32::MAX, end_instant, context)
+
+}
+
+// Result of one completed iteration of the search
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    // Principal variation, the first move is the one to play
+    pub pv: Vec<Move>,
+    pub score: i32,
+    pub depth: u32,
+    // Nodes visited since the search started
+    pub nodes: u64,
+    pub elapsed: Duration
+}
+
+impl SearchInfo {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().copied()
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line: Vec<String> = self.pv.iter().map(|m| m.to_string()).collect();
+        write!(f, "Depth {} in {:?} with value {} after {} nodes: {}",
+               self.depth, self.elapsed, self.score, self.nodes, line.join(" "))
     }
-    return if state.color == WHITE { ( best_action, alpha, completed ) } else { ( best_action, beta, completed ) };
 }
 
-pub fn iterative_time_bound_alpha_beta_search(state: &State, depth: u32, end_instant: Instant) -> Option<Move> {
-    let mut best_action: Option<Move> = None;
+// Searches a window around the score of the previous iteration, widening the side that
+// fails until the score falls inside it
+fn aspiration_search(state: &State, depth: u32, previous_score: Option<i32>, end_instant: Instant, context: &mut SearchContext) -> (Option<Move>, i32, bool) {
+    let previous_score = match previous_score {
+        Some(score) if context.config.aspiration && score != i32::MIN && score != i32::MAX => score,
+        _ => return time_bound_alpha_beta_search(state, depth, end_instant, context)
+    };
+    let mut low_delta = ASPIRATION_WINDOW;
+    let mut high_delta = ASPIRATION_WINDOW;
+    loop {
+        let alpha = if low_delta > ASPIRATION_MAX_WINDOW { i32::MIN } else { previous_score.saturating_sub(low_delta) };
+        let beta = if high_delta > ASPIRATION_MAX_WINDOW { i32::MAX } else { previous_score.saturating_add(high_delta) };
+        let result = time_bound_alpha_beta_window_search(state, depth, alpha, beta, end_instant, context);
+        if !result.2 {
+            return result;
+        }
+        if result.1 <= alpha && alpha != i32::MIN {
+            low_delta *= 4;
+        } else if result.1 >= beta && beta != i32::MAX {
+            high_delta *= 4;
+        } else {
+            return result;
+        }
+    }
+}
+
+// Iterative deepening from first_depth, only the main thread logs its iterations
+fn iterative_deepening(state: &State, first_depth: u32, depth: u32, end_instant: Instant, context: &mut SearchContext, main_thread: bool) -> Option<SearchInfo> {
+    let mut best_info: Option<SearchInfo> = None;
     let mut best_value: i32 = if state.color == WHITE {
         std::i32::MIN
     } else {
         std::i32::MAX
     };
-    let mut current_depth: u32 = 0;
+    let mut current_depth: u32 = first_depth;
 
     let start_instant = Instant::now();
-    while current_depth <= depth && Instant::now() < end_instant {
-        let result = time_bound_alpha_beta_search(state, current_depth, end_instant);
+    let start_nodes = context.nodes;
+    let mut previous_score: Option<i32> = None;
+    while current_depth <= depth && !context.out_of_time(end_instant) {
+        let result = aspiration_search(state, current_depth, previous_score, end_instant, context);
         let completed = result.2;
         if !completed {
             // info!("Depth {} not completed, discarding it", current_depth);
             break;
         }
-        if best_action.is_none() {
-            best_action = result.0;
+        let info = SearchInfo {
+            pv: context.principal_variation().to_vec(),
+            score: result.1,
+            depth: current_depth,
+            nodes: context.nodes - start_nodes,
+            elapsed: start_instant.elapsed()
+        };
+        if main_thread {
+            info!("{}", info);
+        }
+        previous_score = Some(result.1);
+        if best_info.is_none() || !context.config.best_iteration {
             best_value = result.1;
+            best_info = Some(info);
         }
         else if state.color == WHITE && result.1 > best_value {
-            best_action = result.0;
             best_value = result.1;
+            best_info = Some(info);
             if result.1 == std::i32::MAX {
                 break;
             }
         }
         else if state.color == BLACK && result.1 < best_value {
-            best_action = result.0;
             best_value = result.1;
+            best_info = Some(info);
             if result.1 == std::i32::MIN {
                 break;
             }
@@ -481,17 +1043,42 @@ pub fn iterative_time_bound_alpha_beta_search(state: &State, depth: u32, end_ins
         if (state.color == WHITE && best_value == std::i32::MAX) || (state.color == BLACK && best_value == std::i32::MIN) {
             break;
         }
-        info!("Depth {} in {:?} with chosen move {} with value {}", current_depth, start_instant.elapsed(), result.0.unwrap(), result.1);
         current_depth += 1;
     }
-    best_action
+    best_info
+}
+
+// With more than one thread, helpers search the same position and share the transposition
+// table with the main thread, whose result is the one returned (Lazy SMP)
+pub fn iterative_time_bound_alpha_beta_search(state: &State, depth: u32, end_instant: Instant, context: &mut SearchContext) -> Option<SearchInfo> {
+    if context.threads <= 1 {
+        return iterative_deepening(state, 0, depth, end_instant, context, true);
+    }
+    let mut helpers: Vec<SearchContext> = (1..context.threads).map(|_| context.helper()).collect();
+    let info = thread::scope(|scope| {
+        for (i, helper) in helpers.iter_mut().enumerate() {
+            // Every other helper starts one ply deeper to spread the threads over the depths
+            let first_depth = (i as u32 + 1) % 2;
+            scope.spawn(move || iterative_deepening(state, first_depth, depth, end_instant, helper, false));
+        }
+        let info = iterative_deepening(state, 0, depth, end_instant, context, true);
+        context.stop.store(true, Ordering::Relaxed);
+        info
+    });
+    context.stop.store(false, Ordering::Relaxed);
+    context.nodes += helpers.iter().map(|helper| helper.nodes).sum::<u64>();
+    info
 }
 
 #[allow(dead_code)]
 pub fn search_random(state: &State) -> Move {
+    random_move(state, &mut rand::thread_rng())
+}
+
+// Uniformly random legal move drawn from the given generator
+pub fn random_move<R: Rng>(state: &State, rng: &mut R) -> Move {
     let actions = actions(state);
-    let mut rng = rand::thread_rng();
-    let i: usize = rng.gen_range::<usize, usize, usize>(0, actions.len()-1);
+    let i: usize = rng.gen_range::<usize, usize, usize>(0, actions.len());
     actions.get(i).cloned().unwrap()
 }
 
@@ -499,12 +1086,12 @@ pub fn search_random(state: &State) -> Move {
 mod test{
     use super::*;
     use crate::game::Board;
-    use std::time::Duration;
+    use crate::ruleset::ASHTON;
 
     #[test]
     fn test_heuristic() {
         let mut state = State::init(WHITE.to_string());
-        let mut score: i32 = heuristic(&state);
+        let mut score: i32 = heuristic(&state, &EvalParams::default());
         assert_eq!(score, -10);
 
         state.board = Board::new([
@@ -518,7 +1105,7 @@ mod test{
             [0, 0, 0, 0, 2, 0, 0, 0, 0],
             [0, 0, 0, 2, 2, 2, 0, 0, 0]
         ]);
-        score = heuristic(&state);
+        score = heuristic(&state, &EvalParams::default());
         assert_eq!(score, -20);
 
         let mut state = State::init(BLACK.to_string());
@@ -533,7 +1120,7 @@ mod test{
             [0, 0, 0, 0, 2, 0, 0, 0, 0],
             [0, 0, 0, 2, 2, 2, 0, 0, 0]
         ]);
-        score = heuristic(&state);
+        score = heuristic(&state, &EvalParams::default());
         assert_eq!(score, std::i32::MAX);
 
         let mut state = State::init(BLACK.to_string());
@@ -559,7 +1146,7 @@ mod test{
             }
         };
         state.apply_move(&m);
-        let score = heuristic(&state);
+        let score = heuristic(&state, &EvalParams::default());
         assert_eq!(score, -95);
 
         let mut state = State::init(WHITE.to_string());
@@ -605,7 +1192,7 @@ mod test{
                 y: 2
             }
         });
-        let score = heuristic(&state);
+        let score = heuristic(&state, &EvalParams::default());
         assert_eq!(score, 5000);
 
         let mut state = State::init(BLACK.to_string());
@@ -620,7 +1207,7 @@ mod test{
             [0, 0, 0, 0, 2, 0, 0, 0, 0],
             [0, 0, 0, 0, 2, 0, 0, 0, 0]
         ]);
-        let score1 = heuristic(&state);
+        let score1 = heuristic(&state, &EvalParams::default());
         state.board = Board::new([
             [0, 0, 0, 0, 2, 0, 0, 0, 0],
             [0, 0, 0, 0, 0, 0, 0, 2, 0],
@@ -632,8 +1219,23 @@ mod test{
             [0, 0, 0, 0, 2, 0, 0, 0, 0],
             [0, 0, 0, 2, 2, 0, 0, 0, 0]
         ]);
-        let score2 = heuristic(&state);
-        assert!(score1 > score2);
+        let score2 = heuristic(&state, &EvalParams::default());
+        // The checkers that moved are not next to the king, the heuristic does not tell them apart
+        assert_eq!(score1, score2);
+        state.board = Board::new([
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 2, 0],
+            [0, 0, 0, 0, 1, 2, 0, 0, 0],
+            [0, 0, 0, 2, 0, 3, 0, 0, 2],
+            [2, 2, 0, 2, 0, 2, 1, 2, 2],
+            [0, 0, 2, 1, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 2, 2, 0, 0, 0, 0]
+        ]);
+        // Black closes in below the king
+        let score3 = heuristic(&state, &EvalParams::default());
+        assert_eq!(score2 - score3, EvalParams::default().black_around_king);
     }
 
     #[test]
@@ -651,7 +1253,7 @@ mod test{
             [0, 0, 0, 2, 2, 0, 0, 0, 0]
         ]);
         let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
-        let chosen_move = time_bound_alpha_beta_search(&state, 2, end_instant);
+        let chosen_move = time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB));
         let predicted_move = Move {
             from: Position {
                 x: 8,
@@ -681,7 +1283,8 @@ mod test{
             [0, 0, 0, 2, 2, 2, 0, 0, 0]
         ]);
         let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
-        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant);
+        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB))
+            .and_then(|info| info.best_move());
         let predicted_move = Move {
             from: Position {
                 x: 2,
@@ -708,7 +1311,8 @@ mod test{
             [0, 0, 0, 2, 2, 0, 0, 0, 0]
         ]);
         let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
-        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant);
+        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB))
+            .and_then(|info| info.best_move());
         let predicted_move = Move {
             from: Position {
                 x: 8,
@@ -735,7 +1339,8 @@ mod test{
             [0, 0, 0, 0, 2, 0, 0, 0, 0]
         ]);
         let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
-        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant);
+        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB))
+            .and_then(|info| info.best_move());
         let predicted_move = Move {
             from: Position {
                 x: 1,
@@ -762,7 +1367,8 @@ mod test{
             [0, 0, 0, 2, 2, 0, 0, 0, 0]
         ]);
         let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
-        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant);
+        let chosen_move = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB))
+            .and_then(|info| info.best_move());
         let predicted_move = Move {
             from: Position {
                 x: 4,
@@ -776,4 +1382,160 @@ mod test{
         assert!(chosen_move.is_some());
         assert_eq!(chosen_move.unwrap(), predicted_move);
     }
+
+    // Positions on which the search enhancements are measured
+    fn node_test_positions() -> Vec<State> {
+        [
+            "B8/9/6BW1/B1K1W3B/BBWW1WWBB/B3W1B2/4W4/4B4/3BBB3 w",
+            "3BB4/4B4/4WB3/B2WK3B/BBWW1B1BB/B2WB4/9/4B4/3BB4 b",
+            "4B4/4B4/1WB2B3/B1K2B3/BB1B1B1BB/2B1B4/9/4B4/4B4 b",
+            "4B4/7B1/4WB3/3B1K2B/BB1B2WBB/2BWB4/9/4B4/3BB4 b"
+        ].iter().map(|notation| State::from_notation(notation, &ASHTON).unwrap()).collect()
+    }
+
+    // Nodes searched to depth over the test positions with the given configuration, and the
+    // result found in each of them
+    fn nodes_with(depth: u32, config: impl Fn(&mut SearchConfig)) -> (u64, Vec<SearchInfo>) {
+        let mut nodes = 0;
+        let mut infos: Vec<SearchInfo> = vec![];
+        for state in node_test_positions() {
+            let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+            let mut context = SearchContext::new(DEFAULT_TT_SIZE_MB);
+            config(&mut context.config);
+            infos.push(iterative_time_bound_alpha_beta_search(&state, depth, end_instant, &mut context).unwrap());
+            nodes += context.nodes;
+        }
+        (nodes, infos)
+    }
+
+    #[test]
+    fn test_null_move_lmr_nodes() {
+        let (nodes, infos) = nodes_with(4, |_| {});
+        let (plain_nodes, plain_infos) = nodes_with(4, |config| {
+            config.null_move = false;
+            config.late_move_reductions = false;
+        });
+        for (state, (info, plain_info)) in node_test_positions().iter().zip(infos.iter().zip(plain_infos.iter())) {
+            assert!(legal_moves(state).contains(&info.best_move().unwrap()));
+            assert!(legal_moves(state).contains(&plain_info.best_move().unwrap()));
+        }
+        assert!(nodes < plain_nodes, "{} >= {}", nodes, plain_nodes);
+    }
+
+    #[test]
+    fn test_pvs_aspiration_nodes() {
+        let (nodes, infos) = nodes_with(3, |_| {});
+        let (plain_nodes, plain_infos) = nodes_with(3, |config| {
+            config.pvs = false;
+            config.aspiration = false;
+        });
+        for (info, plain_info) in infos.iter().zip(plain_infos.iter()) {
+            assert_eq!(info.score, plain_info.score);
+        }
+        assert!(nodes < plain_nodes, "{} >= {}", nodes, plain_nodes);
+    }
+
+    #[test]
+    fn test_move_ordering_nodes() {
+        let mut ordered_nodes = 0;
+        let mut unordered_nodes = 0;
+        for state in node_test_positions() {
+            let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+
+            let mut ordered = SearchContext::new(DEFAULT_TT_SIZE_MB);
+            let ordered_result = time_bound_alpha_beta_search(&state, 2, end_instant, &mut ordered);
+            ordered_nodes += ordered.nodes;
+
+            let mut unordered = SearchContext::new(DEFAULT_TT_SIZE_MB);
+            unordered.config.move_ordering = false;
+            let unordered_result = time_bound_alpha_beta_search(&state, 2, end_instant, &mut unordered);
+            unordered_nodes += unordered.nodes;
+
+            assert_eq!(ordered_result.1, unordered_result.1);
+        }
+        assert!(ordered_nodes < unordered_nodes, "{} >= {}", ordered_nodes, unordered_nodes);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let mut state = State::init(WHITE.to_string());
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        let info = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB)).unwrap();
+        assert_eq!(info.depth, 2);
+        assert!(info.nodes > 0);
+        assert!(!info.pv.is_empty() && info.pv.len() <= 3);
+        assert_eq!(info.best_move(), info.pv.first().copied());
+
+        // Every move of the line is legal for the side to move
+        for m in info.pv.iter() {
+            assert!(legal_moves(&state).contains(m), "{} is not legal", m);
+            state = result(&state, m);
+        }
+    }
+
+    #[test]
+    fn test_single_thread_is_deterministic() {
+        let state = State::init(BLACK.to_string());
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        let first = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB)).unwrap();
+        let second = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut SearchContext::new(DEFAULT_TT_SIZE_MB)).unwrap();
+        assert_eq!(first.pv, second.pv);
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.nodes, second.nodes);
+    }
+
+    #[test]
+    fn test_parallel_search() {
+        let state = State::init(WHITE.to_string());
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        let mut context = SearchContext::new(DEFAULT_TT_SIZE_MB);
+        context.threads = 4;
+        let info = iterative_time_bound_alpha_beta_search(&state, 2, end_instant, &mut context).unwrap();
+        assert!(legal_moves(&state).contains(&info.best_move().unwrap()));
+        // Helper nodes are added to the context once they have stopped
+        assert!(context.nodes >= info.nodes);
+        assert!(!context.stop.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // The king has a single line to the escape at (2,0)
+        let mut state = State::init(WHITE.to_string());
+        state.board = Board::new([
+            [0, 0, 0, 2, 2, 2, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 0, 3, 0, 0, 0, 0, 0, 2],
+            [2, 2, 0, 0, 0, 0, 0, 2, 2],
+            [2, 0, 0, 0, 0, 0, 0, 0, 2],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 2, 0, 0, 0, 0],
+            [0, 0, 2, 2, 2, 2, 0, 0, 0],
+        ]);
+        state.history.push(state.board);
+        state.history_keys.push(state.board.key());
+        let stand_pat = heuristic(&state, &EvalParams::default());
+        assert_ne!(stand_pat, i32::MAX);
+
+        let mut context = SearchContext::new(DEFAULT_TT_SIZE_MB);
+        let mut budget = QUIESCENCE_NODE_LIMIT;
+        assert_eq!(quiescence(&mut state.clone(), i32::MIN, i32::MAX, 0, &mut budget, &mut context), i32::MAX);
+        assert!(budget < QUIESCENCE_NODE_LIMIT);
+
+        // Without budget the leaf is not extended
+        let mut budget = 0;
+        assert_eq!(quiescence(&mut state.clone(), i32::MIN, i32::MAX, 0, &mut budget, &mut context), stand_pat);
+
+        // At depth 0 black sees the escape only through quiescence and blocks it
+        state.color = BLACK.to_string();
+        state.board.set_turn(BLACK);
+        let end_instant = Instant::now().checked_add(Duration::new(60, 0)).unwrap();
+        for &quiescence in [false, true].iter() {
+            let mut context = SearchContext::new(DEFAULT_TT_SIZE_MB);
+            context.config.quiescence = quiescence;
+            let m = time_bound_alpha_beta_search(&state, 0, end_instant, &mut context).0.unwrap();
+            let escapes = get_king_escapes(&result(&state, &m), Position { x: 2, y: 3 });
+            assert_eq!(escapes == 0, quiescence, "{} leaves {} escapes", m, escapes);
+        }
+    }
 }
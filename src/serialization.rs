@@ -1,5 +1,6 @@
 use crate::constants::*;
-use crate::game::{Move, Board};
+use crate::game::{Move, Board, Position};
+use crate::ruleset::Ruleset;
 use crate::serde::{Serialize, Deserialize};
 use crate::serde_json::{Value, Map};
 
@@ -20,12 +21,37 @@ pub fn serialize_move(m: &Move, color: &str) -> String {
     serde_json::to_string(&sm).unwrap()
 }
 
-pub fn deserialize_board(input: &String) -> Board {
+// Move sent by a player, with the color it claims to play
+pub fn deserialize_move(input: &str) -> Result<(Move, String), String> {
+    let sm: ServerMove = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let from: Position = sm.from.parse()?;
+    let to: Position = sm.to.parse()?;
+    Ok((Move { from, to }, sm.turn.to_lowercase()))
+}
+
+// Game state as the server sends it, turn is WHITE, BLACK, WHITEWIN, BLACKWIN or DRAW
+pub fn serialize_state(board: &Board, turn: &str) -> String {
+    let rows: Vec<Vec<&str>> = (0..board.size()).map(|y| {
+        (0..board.size()).map(|x| {
+            let p = Position { x, y };
+            match board.cell_content(p) {
+                W => "WHITE",
+                B => "BLACK",
+                K => "KING",
+                _ if board.cell_type(p) == T => "THRONE",
+                _ => "EMPTY"
+            }
+        }).collect()
+    }).collect();
+    serde_json::json!({ "board": rows, "turn": turn.to_uppercase() }).to_string()
+}
+
+pub fn deserialize_board(input: &String, rules: &'static Ruleset) -> Board {
     let wrapper: Value = serde_json::from_str(&input).unwrap();
     let data: &Map<String, Value> = wrapper.as_object().unwrap();
     let matrix: &Vec<Value> = data.get("board").unwrap().as_array().unwrap();
 
-    let mut board= [[0u32; 9]; 9];
+    let mut board = vec![vec![0u32; rules.size as usize]; rules.size as usize];
     for (y, row_wrapper) in matrix.iter().enumerate() {
         let row: &Vec<Value>= row_wrapper.as_array().unwrap();
         for (x, cell_wrapper) in row.iter().enumerate() {
@@ -41,7 +67,7 @@ pub fn deserialize_board(input: &String) -> Board {
             }
         }
     }
-    Board::new(board)
+    Board::from_cells(rules, &board)
 }
 
 pub fn deserialize_turn(input: &String) -> String {
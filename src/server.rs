@@ -0,0 +1,195 @@
+use crate::constants::*;
+use crate::game::State;
+use crate::network::ServerConnection;
+use crate::record::{GameRecord, Outcome, outcome};
+use crate::rules::legal_move;
+use crate::ruleset::Ruleset;
+use crate::serialization::{deserialize_move, serialize_state};
+use std::io::Error;
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+use log::info;
+use chrono::Local;
+
+// Server side of the tournament protocol: white and black connect on their own ports and
+// send their names, then both receive every state and the side to move answers with a move
+pub struct Server {
+    white_listener: TcpListener,
+    black_listener: TcpListener
+}
+
+impl Server {
+    pub fn bind(address: &str, white_port: u32, black_port: u32) -> Result<Server, Error> {
+        let white_listener = TcpListener::bind(format!("{}:{}", address, white_port))?;
+        let black_listener = TcpListener::bind(format!("{}:{}", address, black_port))?;
+        info!("Waiting for white on {} and black on {}", white_listener.local_addr()?, black_listener.local_addr()?);
+        Ok(Server { white_listener, black_listener })
+    }
+
+    // Addresses of the white and black ports
+    #[allow(dead_code)]
+    pub fn local_addrs(&self) -> Result<(SocketAddr, SocketAddr), Error> {
+        Ok((self.white_listener.local_addr()?, self.black_listener.local_addr()?))
+    }
+
+    // Plays one game. A player that sends an illegal move, does not answer within the
+    // timeout or disconnects loses, whether the server notices it on a read or on a write.
+    pub fn play(&self, rules: &'static Ruleset, timeout: Duration) -> Result<GameRecord, Error> {
+        let mut white = ServerConnection::accept(&self.white_listener)?;
+        let white_name = white.try_read_string()?;
+        info!("White player: {}", white_name);
+        let mut black = ServerConnection::accept(&self.black_listener)?;
+        let black_name = black.try_read_string()?;
+        info!("Black player: {}", black_name);
+        white.set_timeout(Some(timeout))?;
+        black.set_timeout(Some(timeout))?;
+
+        let mut record = GameRecord::new(&white_name, &black_name, &Local::now().format("%Y.%m.%d").to_string(), rules);
+        let mut state = State::with_rules(rules.first_turn.to_string(), rules);
+        loop {
+            let message = serialize_state(&state.board, &state.turn);
+            let sent = white.try_write_string(&message).map_err(|e| (WHITE, e))
+                .and_then(|_| black.try_write_string(&message).map_err(|e| (BLACK, e)));
+            if let Err((color, e)) = sent {
+                info!("{} loses: {}", color, e);
+                record.result = if color == WHITE { Outcome::BlackWin } else { Outcome::WhiteWin };
+                record.termination = format!("connection lost: {}", e);
+                break;
+            }
+
+            let player = if state.turn == WHITE { &mut white } else { &mut black };
+            let answer = player.try_read_string().map_err(|e| e.to_string())
+                .and_then(|answer| deserialize_move(&answer));
+            let error = match answer {
+                Ok((m, color)) if color == state.turn && legal_move(&state, &m) => {
+                    info!("{} plays {}", state.turn, m);
+                    record.moves.push(m);
+                    state.make_move(&m);
+                    let (result, _) = outcome(&state);
+                    if result == Outcome::Unfinished {
+                        continue;
+                    }
+                    record.finish(&state);
+                    break;
+                },
+                Ok((m, color)) => format!("illegal move {} for {}", m, color),
+                Err(e) => e
+            };
+            info!("{} loses: {}", state.turn, error);
+            record.result = if state.turn == WHITE { Outcome::BlackWin } else { Outcome::WhiteWin };
+            record.termination = error;
+            break;
+        }
+
+        let turn = match record.result {
+            Outcome::WhiteWin => "whitewin",
+            Outcome::BlackWin => "blackwin",
+            _ => "draw"
+        };
+        info!("Game ended {} ({})", record.result, record.termination);
+        // The result reaches whoever is still connected
+        let message = serialize_state(&state.board, turn);
+        white.try_write_string(&message).ok();
+        black.try_write_string(&message).ok();
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Move;
+    use crate::ruleset::ASHTON;
+    use crate::serialization::{deserialize_board, deserialize_turn, serialize_move};
+    use std::thread;
+
+    // Connects as a player and answers each of its turns with the next scripted move
+    fn scripted_player(address: SocketAddr, color: &'static str, moves: Vec<&'static str>) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let mut connection = ServerConnection::connect(&address.ip().to_string(), address.port() as u32).unwrap();
+            connection.write_string(&color.to_string());
+            let mut moves = moves.into_iter();
+            loop {
+                let message = connection.read_string();
+                let turn = deserialize_turn(&message);
+                deserialize_board(&message, &ASHTON);
+                if turn != WHITE && turn != BLACK {
+                    return turn;
+                }
+                if turn == color {
+                    let m: Move = moves.next().unwrap().parse().unwrap();
+                    connection.write_string(&serialize_move(&m, color));
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_serve_game() {
+        let server = Server::bind("127.0.0.1", 0, 0).unwrap();
+        let (white_address, black_address) = server.local_addrs().unwrap();
+        let white = scripted_player(white_address, WHITE, vec!["e3->h3", "e4->b4", "e5->e3", "e3->a3"]);
+        let black = scripted_player(black_address, BLACK, vec!["d1->d2", "e2->g2", "d2->c2"]);
+        let record = server.play(&ASHTON, Duration::from_secs(10)).unwrap();
+        assert_eq!(white.join().unwrap(), "whitewin");
+        assert_eq!(black.join().unwrap(), "whitewin");
+        assert_eq!((record.white.as_str(), record.black.as_str()), (WHITE, BLACK));
+        assert_eq!(record.moves.len(), 7);
+        assert_eq!(record.result, Outcome::WhiteWin);
+        assert_eq!(record.termination, "king escaped");
+    }
+
+    #[test]
+    fn test_serve_disconnect() {
+        let server = Server::bind("127.0.0.1", 0, 0).unwrap();
+        let (white_address, black_address) = server.local_addrs().unwrap();
+        let white = scripted_player(white_address, WHITE, vec!["e3->h3"]);
+        // Black leaves after the first state
+        let black = thread::spawn(move || {
+            let mut connection = ServerConnection::connect(&black_address.ip().to_string(), black_address.port() as u32).unwrap();
+            connection.write_string(&BLACK.to_string());
+            connection.read_string();
+        });
+        let record = server.play(&ASHTON, Duration::from_secs(10)).unwrap();
+        black.join().unwrap();
+        assert_eq!(white.join().unwrap(), "whitewin");
+        assert_eq!(record.moves.len(), 1);
+        assert_eq!(record.result, Outcome::WhiteWin);
+        assert!(record.termination.starts_with("connection lost"), "{}", record.termination);
+    }
+
+    #[test]
+    fn test_serve_oversized_message() {
+        let server = Server::bind("127.0.0.1", 0, 0).unwrap();
+        let (white_address, black_address) = server.local_addrs().unwrap();
+        let white = scripted_player(white_address, WHITE, vec!["e3->h3"]);
+        // Black answers with the length of a 4 GiB message
+        let black = thread::spawn(move || {
+            let mut connection = ServerConnection::connect(&black_address.ip().to_string(), black_address.port() as u32).unwrap();
+            connection.write_string(&BLACK.to_string());
+            connection.read_string();
+            connection.read_string();
+            connection.write_int(u32::MAX);
+            deserialize_turn(&connection.read_string())
+        });
+        let record = server.play(&ASHTON, Duration::from_secs(10)).unwrap();
+        assert_eq!(black.join().unwrap(), "whitewin");
+        assert_eq!(white.join().unwrap(), "whitewin");
+        assert_eq!(record.result, Outcome::WhiteWin);
+        assert!(record.termination.contains("too long"), "{}", record.termination);
+    }
+
+    #[test]
+    fn test_serve_illegal_move() {
+        let server = Server::bind("127.0.0.1", 0, 0).unwrap();
+        let (white_address, black_address) = server.local_addrs().unwrap();
+        let white = scripted_player(white_address, WHITE, vec!["e3->h3", "e5->e4"]);
+        let black = scripted_player(black_address, BLACK, vec!["d1->d2"]);
+        let record = server.play(&ASHTON, Duration::from_secs(10)).unwrap();
+        assert_eq!(white.join().unwrap(), "blackwin");
+        assert_eq!(black.join().unwrap(), "blackwin");
+        assert_eq!(record.moves.len(), 2);
+        assert_eq!(record.result, Outcome::BlackWin);
+        assert_eq!(record.termination, "illegal move e5->e4 for white");
+    }
+}
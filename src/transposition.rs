@@ -0,0 +1,206 @@
+use crate::game::{Move, Position};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // Score is at least the stored one (fail high)
+    Lower,
+    // Score is at most the stored one (fail low)
+    Upper
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u32,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<Move>
+}
+
+// Field layout of the packed data word of a slot
+const SCORE_BITS: u64 = 0xffff_ffff;
+const DEPTH_SHIFT: u32 = 32;
+const BOUND_SHIFT: u32 = 40;
+const MOVE_FLAG: u64 = 1 << 42;
+const MOVE_SHIFT: u32 = 43;
+// Set in every stored entry so that an empty slot never matches a key
+const USED_FLAG: u64 = 1 << 63;
+
+fn pack(entry: &Entry) -> u64 {
+    let bound: u64 = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2
+    };
+    let mut data = USED_FLAG | (entry.score as u32 as u64) | ((entry.depth.min(255) as u64) << DEPTH_SHIFT) | (bound << BOUND_SHIFT);
+    if let Some(m) = entry.best_move {
+        let coordinates = (m.from.x << 12 | m.from.y << 8 | m.to.x << 4 | m.to.y) as u64;
+        data |= MOVE_FLAG | (coordinates << MOVE_SHIFT);
+    }
+    data
+}
+
+fn unpack(key: u64, data: u64) -> Entry {
+    let bound = match (data >> BOUND_SHIFT) & 3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper
+    };
+    let best_move = if data & MOVE_FLAG != 0 {
+        let c = ((data >> MOVE_SHIFT) & 0xffff) as u32;
+        Some(Move {
+            from: Position { x: c >> 12, y: (c >> 8) & 0xf },
+            to: Position { x: (c >> 4) & 0xf, y: c & 0xf }
+        })
+    } else {
+        None
+    };
+    Entry {
+        key,
+        depth: ((data >> DEPTH_SHIFT) & 0xff) as u32,
+        bound,
+        score: (data & SCORE_BITS) as u32 as i32,
+        best_move
+    }
+}
+
+// The key is stored xored with the data, so a slot torn by two threads writing
+// at the same time fails the key check instead of returning a mixed entry
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64
+}
+
+// Fixed size hash table indexed by the low bits of the Zobrist key.
+// It can be shared between search threads without locking.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    mask: usize
+}
+
+impl TranspositionTable {
+    // Size is rounded down to a power of two number of entries
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let bytes = size_mb.max(1) * 1024 * 1024;
+        let mut count: usize = 1;
+        while count * 2 * size_of::<Slot>() <= bytes {
+            count *= 2;
+        }
+        TranspositionTable {
+            slots: (0..count).map(|_| Slot::default()).collect(),
+            mask: count - 1
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = &self.slots[key as usize & self.mask];
+        let data = slot.data.load(Ordering::Relaxed);
+        if data & USED_FLAG != 0 && slot.key.load(Ordering::Relaxed) ^ data == key {
+            Some(unpack(key, data))
+        } else {
+            None
+        }
+    }
+
+    // Keeps a deeper entry of the same position, replaces anything else
+    pub fn store(&self, entry: Entry) {
+        if let Some(old) = self.probe(entry.key) {
+            if old.depth > entry.depth {
+                return;
+            }
+        }
+        let slot = &self.slots[entry.key as usize & self.mask];
+        let data = pack(&entry);
+        slot.key.store(entry.key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // Forgets every entry, for scores that no longer hold
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        assert!(tt.capacity().is_power_of_two());
+        let best_move = Some(Move { from: Position { x: 4, y: 2 }, to: Position { x: 1, y: 2 } });
+        let key: u64 = 0xdead_beef;
+        assert!(tt.probe(key).is_none());
+
+        tt.store(Entry { key, depth: 3, bound: Bound::Exact, score: 42, best_move });
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.score, 42);
+        assert_eq!(entry.best_move, best_move);
+
+        // Shallower results do not replace deeper ones
+        tt.store(Entry { key, depth: 1, bound: Bound::Lower, score: 7, best_move: None });
+        assert_eq!(tt.probe(key).unwrap().depth, 3);
+
+        // Another position in the same slot replaces it
+        let other_key = key + tt.capacity() as u64;
+        tt.store(Entry { key: other_key, depth: 0, bound: Bound::Upper, score: -5, best_move: None });
+        assert!(tt.probe(key).is_none());
+        assert_eq!(tt.probe(other_key).unwrap().score, -5);
+
+        tt.clear();
+        assert!(tt.probe(other_key).is_none());
+    }
+
+    #[test]
+    fn test_pack_unpack() {
+        let entries = [
+            Entry { key: 1, depth: 7, bound: Bound::Lower, score: std::i32::MIN, best_move: None },
+            Entry { key: 2, depth: 0, bound: Bound::Upper, score: std::i32::MAX, best_move: Some(Move { from: Position { x: 8, y: 0 }, to: Position { x: 0, y: 8 } }) },
+            Entry { key: 3, depth: 2, bound: Bound::Exact, score: -123, best_move: Some(Move { from: Position { x: 4, y: 1 }, to: Position { x: 4, y: 3 } }) }
+        ];
+        for entry in entries.iter() {
+            let unpacked = unpack(entry.key, pack(entry));
+            assert_eq!(unpacked.depth, entry.depth);
+            assert_eq!(unpacked.bound, entry.bound);
+            assert_eq!(unpacked.score, entry.score);
+            assert_eq!(unpacked.best_move, entry.best_move);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_store() {
+        // Every thread writes entries whose score is derived from the key into the same few slots
+        let tt = Arc::new(TranspositionTable::new(1));
+        let capacity = tt.capacity() as u64;
+        let handles: Vec<_> = (0..4u64).map(|t| {
+            let tt = Arc::clone(&tt);
+            thread::spawn(move || {
+                for i in 0..10_000u64 {
+                    let key = (i % 8) + capacity * (t + 1);
+                    tt.store(Entry { key, depth: (i % 5) as u32, bound: Bound::Exact, score: key as i32, best_move: None });
+                    if let Some(entry) = tt.probe(key) {
+                        assert_eq!(entry.score, key as i32);
+                    }
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::constants::*;
+use crate::eval::{EvalParams, Evaluator};
+use crate::game::{State, Status};
+use crate::record::{GameRecord, Outcome};
+use crate::rules::game_status;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+
+// A position with the result of its game for white: 1 win, 0.5 draw, 0 loss
+pub struct Sample {
+    pub state: State,
+    pub result: f64
+}
+
+// Ongoing positions of a finished game after the first skip plies. The history is cut down to
+// the current board, the only one the heuristic looks at.
+pub fn game_samples(record: &GameRecord, skip: usize) -> Result<Vec<Sample>, String> {
+    let result = match record.result {
+        Outcome::WhiteWin => 1.0,
+        Outcome::BlackWin => 0.0,
+        Outcome::Draw => 0.5,
+        Outcome::Unfinished => return Ok(vec![])
+    };
+    let mut samples: Vec<Sample> = vec![];
+    for state in record.states()?.into_iter().skip(skip) {
+        if game_status(&state) != Status::ONGOING {
+            continue;
+        }
+        let mut state = state;
+        state.history.drain(..state.history.len() - 1);
+        state.history_keys.drain(..state.history_keys.len() - 1);
+        samples.push(Sample { state, result });
+    }
+    Ok(samples)
+}
+
+// Samples of every game record in the given files and directories
+pub fn load_samples(paths: &[PathBuf], skip: usize) -> Result<Vec<Sample>, String> {
+    let mut samples: Vec<Sample> = vec![];
+    for path in paths {
+        let mut files: Vec<PathBuf> = if path.is_dir() {
+            fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.is_file())
+                .collect()
+        } else {
+            vec![path.clone()]
+        };
+        files.sort();
+        for file in files {
+            let record = GameRecord::load(&file)?;
+            samples.extend(game_samples(&record, skip).map_err(|e| format!("{}: {}", file.display(), e))?);
+        }
+    }
+    Ok(samples)
+}
+
+// Expected result for white of a heuristic value, k scales the value
+fn sigmoid(value: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * value as f64 / 400.0))
+}
+
+// Mean squared difference between the results and the expected results of the evaluation,
+// computed on jobs threads
+pub fn error(samples: &[Sample], evaluator: &dyn Evaluator, params: &EvalParams, k: f64, jobs: usize) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let chunk = samples.len().div_ceil(jobs.max(1));
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = samples.chunks(chunk).map(|samples| scope.spawn(move || {
+            samples.iter().map(|sample| (sample.result - sigmoid(evaluator.evaluate(&sample.state, params), k)).powi(2)).sum::<f64>()
+        })).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+    });
+    total / samples.len() as f64
+}
+
+// Scale of the sigmoid that fits the results best with the given weights, by ternary search
+pub fn fit_scale(samples: &[Sample], evaluator: &dyn Evaluator, params: &EvalParams, jobs: usize) -> f64 {
+    let (mut low, mut high) = (0.0, 10.0);
+    for _ in 0..40 {
+        let first = low + (high - low) / 3.0;
+        let second = high - (high - low) / 3.0;
+        if error(samples, evaluator, params, first, jobs) < error(samples, evaluator, params, second, jobs) {
+            high = second;
+        } else {
+            low = first;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Local search over the weights: each pass tries to move every weight up or down by the step,
+// keeping changes that lower the error, and halves the step after a pass without any. Stops
+// after a pass without change at step 1 or max_passes passes. report gets the weights and the
+// error after every pass.
+pub fn tune<F: FnMut(usize, &EvalParams, f64)>(samples: &[Sample], evaluator: &dyn Evaluator, start: &EvalParams, k: f64, max_passes: usize, jobs: usize, mut report: F) -> EvalParams {
+    let mut values = start.values();
+    let mut best_error = error(samples, evaluator, start, k, jobs);
+    let mut step = TUNER_FIRST_STEP;
+    for pass in 1..=max_passes {
+        let mut improved = false;
+        for i in 0..values.len() {
+            for delta in [step, -step].iter() {
+                values[i] += delta;
+                let candidate = error(samples, evaluator, &EvalParams::from_values(&values), k, jobs);
+                if candidate < best_error {
+                    best_error = candidate;
+                    improved = true;
+                    break;
+                }
+                values[i] -= delta;
+            }
+        }
+        report(pass, &EvalParams::from_values(&values), best_error);
+        if !improved {
+            if step == 1 {
+                break;
+            }
+            step /= 2;
+        }
+    }
+    EvalParams::from_values(&values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::EscapeEvaluator;
+    use crate::record::escape_game;
+
+    #[test]
+    fn test_game_samples() {
+        let record = escape_game();
+        let samples = game_samples(&record, 2).unwrap();
+        // The last position is over and not a sample
+        assert_eq!(samples.len(), 5);
+        assert!(samples.iter().all(|sample| sample.result == 1.0 && sample.state.history.len() == 1));
+        let mut unfinished = record.clone();
+        unfinished.result = Outcome::Unfinished;
+        assert!(game_samples(&unfinished, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tune() {
+        let mut samples = game_samples(&escape_game(), 0).unwrap();
+        let mut lost = escape_game();
+        lost.result = Outcome::BlackWin;
+        samples.extend(game_samples(&lost, 4).unwrap());
+        let start = EvalParams::default();
+        let k = fit_scale(&samples, &EscapeEvaluator, &start, 2);
+        assert!(k > 0.0 && k < 10.0);
+        let start_error = error(&samples, &EscapeEvaluator, &start, k, 2);
+        assert!((error(&samples, &EscapeEvaluator, &start, k, 1) - start_error).abs() < 1e-12);
+
+        let mut passes = 0;
+        let tuned = tune(&samples, &EscapeEvaluator, &start, k, 3, 2, |pass, params, error| {
+            passes = pass;
+            assert!(error <= start_error);
+            assert!(error < start_error || params == &start);
+        });
+        assert!(passes > 0 && passes <= 3);
+        assert!(error(&samples, &EscapeEvaluator, &tuned, k, 2) < start_error);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::bitboard::{Bitboard, CELLS, cell_index};
+
+// Fixed seed so that keys are the same on every run
+const SEED: u64 = 0x6d75_7363_6f76_6974;
+
+const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn piece_keys(piece: u64) -> [u64; CELLS as usize] {
+    let mut keys = [0u64; CELLS as usize];
+    let mut i = 0;
+    while i < CELLS as usize {
+        keys[i] = splitmix64(SEED ^ splitmix64(piece * CELLS as u64 + i as u64));
+        i += 1;
+    }
+    keys
+}
+
+pub const WHITE_KEYS: [u64; CELLS as usize] = piece_keys(1);
+pub const BLACK_KEYS: [u64; CELLS as usize] = piece_keys(2);
+pub const KING_KEYS: [u64; CELLS as usize] = piece_keys(3);
+pub const BLACK_TO_MOVE_KEY: u64 = splitmix64(SEED ^ 0xb1ac);
+
+// Xor of the keys of every cell of the bitboard
+pub fn bitboard_key(bitboard: Bitboard, keys: &[u64; CELLS as usize]) -> u64 {
+    bitboard.iter().fold(0, |acc, p| acc ^ keys[cell_index(p)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_distinct() {
+        let mut keys: Vec<u64> = vec![BLACK_TO_MOVE_KEY];
+        keys.extend_from_slice(&WHITE_KEYS);
+        keys.extend_from_slice(&BLACK_KEYS);
+        keys.extend_from_slice(&KING_KEYS);
+        let count = keys.len();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), count);
+    }
+}